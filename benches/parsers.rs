@@ -0,0 +1,140 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use soong_digest::ansi::strip_ansi_escape;
+use soong_digest::output::{render_items, OutputFormat};
+use soong_digest::{error, input, warning};
+use std::fmt::Write;
+use termcolor::Buffer;
+
+/// The number of build steps in the synthetic logs.
+const STEPS: usize = 10_000;
+
+/// A verbose.log where every step but the last few prints a warning, colored the way clang
+/// prints it with -fcolor-diagnostics if `color` is set.
+fn verbose_log(color: bool) -> String {
+    let (bold, magenta, reset) = match color {
+        true => ("\u{1b}[1m", "\u{1b}[0;1;35m", "\u{1b}[0m"),
+        false => ("", "", ""),
+    };
+    let mut log = String::new();
+    for i in 1..=STEPS {
+        writeln!(
+            log,
+            "[{}/{}] clang++ -c -o out/obj/foo{}.o foo{}.cpp",
+            i, STEPS, i, i
+        )
+        .unwrap();
+        if i > STEPS - 10 {
+            continue;
+        }
+        writeln!(
+            log,
+            "{}foo{}.cpp:{}:9: {}{}warning: {}{}unused variable 'x' [-Wunused-variable]{}",
+            bold,
+            i,
+            i % 500 + 1,
+            reset,
+            magenta,
+            reset,
+            bold,
+            reset
+        )
+        .unwrap();
+        writeln!(log, "    int x = {};", i).unwrap();
+        writeln!(log, "        ^").unwrap();
+        writeln!(log, "1 warning generated.").unwrap();
+    }
+    log
+}
+
+/// An error.log with one FAILED block per step.
+fn error_log() -> String {
+    let mut log = String::new();
+    for i in 1..=STEPS {
+        writeln!(log, "FAILED: out/obj/foo{}.o", i).unwrap();
+        writeln!(log, "Outputs: out/obj/foo{}.o", i).unwrap();
+        writeln!(log, "Error: exited with code: 1").unwrap();
+        writeln!(
+            log,
+            "Command: clang++ -c -o out/obj/foo{}.o foo{}.cpp",
+            i, i
+        )
+        .unwrap();
+        writeln!(log, "Output:").unwrap();
+        writeln!(
+            log,
+            "foo{}.cpp:{}:5: error: use of undeclared identifier 'y'",
+            i,
+            i % 500 + 1
+        )
+        .unwrap();
+        writeln!(log, "    y = {};", i).unwrap();
+        writeln!(log, "    ^").unwrap();
+        writeln!(log, "1 error generated.").unwrap();
+        writeln!(log).unwrap();
+    }
+    log
+}
+
+fn bench_error_parse(c: &mut Criterion) {
+    let log = error_log();
+    c.bench_function("error-parse", move |b| {
+        b.iter(|| error::parse(&log).unwrap().count())
+    });
+}
+
+fn bench_warning_parse(c: &mut Criterion) {
+    let log = verbose_log(false);
+    c.bench_function("warning-parse", move |b| {
+        b.iter(|| warning::parse(&log).unwrap().count())
+    });
+}
+
+fn bench_warning_parse_color(c: &mut Criterion) {
+    let log = verbose_log(true);
+    c.bench_function("warning-parse-color", move |b| {
+        b.iter(|| warning::parse(&log).unwrap().count())
+    });
+}
+
+/// A colored log as the binary parses it: stripped once when it is read, so that the items borrow
+/// from the stripped text.
+fn bench_warning_parse_color_decoded(c: &mut Criterion) {
+    let log = input::decode(verbose_log(true).as_bytes())
+        .unwrap()
+        .contents;
+    c.bench_function("warning-parse-color-decoded", move |b| {
+        b.iter(|| warning::parse(&log).unwrap().count())
+    });
+}
+
+fn bench_strip_ansi_escape(c: &mut Criterion) {
+    let log = verbose_log(true);
+    c.bench_function("strip-ansi-escape", move |b| {
+        b.iter(|| strip_ansi_escape(&log).len())
+    });
+}
+
+fn bench_render(c: &mut Criterion) {
+    // the items borrow from the log, which has to outlive the benchmark
+    let log: &'static str = Box::leak(verbose_log(false).into_boxed_str());
+    let items = warning::parse(log).unwrap().collect::<Vec<_>>();
+    c.bench_function("render", move |b| {
+        b.iter(|| {
+            let mut buffer = Buffer::ansi();
+            render_items(items.iter().cloned(), OutputFormat::Full, &mut buffer).unwrap()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    // each iteration parses a whole log, so fewer samples still give stable results
+    config = Criterion::default().sample_size(20);
+    targets = bench_error_parse,
+        bench_warning_parse,
+        bench_warning_parse_color,
+        bench_warning_parse_color_decoded,
+        bench_strip_ansi_escape,
+        bench_render
+}
+criterion_main!(benches);
@@ -1,15 +1,22 @@
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::borrow::Cow;
 
-pub fn strip_ansi_escape(input: &str) -> String {
+/// Remove color codes. Logs written without colors are borrowed as is.
+pub fn strip_ansi_escape(input: &str) -> Cow<'_, str> {
     lazy_static! {
-        static ref re: Regex = Regex::new(r"\u{1b}\[\d+(;\d+)*m").unwrap();
+        static ref RE: Regex = Regex::new(r"\u{1b}\[\d+(;\d+)*m").unwrap();
     }
-    re.replace_all(input, "").to_string()
+    if memchr::memchr(0x1b, input.as_bytes()).is_none() {
+        return Cow::Borrowed(input);
+    }
+    RE.replace_all(input, "")
 }
 
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
+
     #[test]
     fn test_strip_ansi_escape() {
         assert_eq!(super::strip_ansi_escape(r"[1m[0m"), "");
@@ -19,5 +26,9 @@ mod tests {
             ),
             "idmap.cpp:234:5: error: foo [-Wfoo]"
         );
+        assert!(matches!(
+            super::strip_ansi_escape("foo.c:1:2: warning: bar"),
+            Cow::Borrowed("foo.c:1:2: warning: bar")
+        ));
     }
 }
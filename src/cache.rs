@@ -0,0 +1,144 @@
+use crate::follow::Kind;
+use crate::hash::stable_hash;
+use crate::item::Item;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The number of parsed logs to keep.
+const CAPACITY: usize = 16;
+
+/// What parsing a log produced.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Entry<'a> {
+    pub items: Vec<Item<'a>>,
+    /// The product found in the contents of the log, see `discover::product`.
+    pub product: Option<String>,
+    /// Whether the log was truncated, see `input::Input`.
+    pub partial: bool,
+}
+
+/// Parsed logs, keyed by a hash of the raw, still compressed, file contents, so that a log that
+/// did not change is neither decompressed nor parsed again.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// A cache in $OUT_DIR/.soong-digest/cache.
+    pub fn new(out_dir: &Path) -> Cache {
+        Cache {
+            dir: out_dir.join(".soong-digest").join("cache"),
+        }
+    }
+
+    pub fn load(&self, key: &str) -> Option<Entry<'static>> {
+        let path = self.dir.join(key);
+        let contents = fs::read_to_string(&path).ok()?;
+        let entry = serde_json::from_str(&contents).ok()?;
+        // mark the entry as recently used, so that pruning keeps it
+        let _ = fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(entry)
+    }
+
+    pub fn store(&self, key: &str, entry: &Entry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(key), serde_json::to_string(entry)?)?;
+        self.prune()
+    }
+
+    /// Remove all but the most recently used entries.
+    fn prune(&self) -> io::Result<()> {
+        let mut files = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            files.push((entry.metadata()?.modified()?, entry.path()));
+        }
+        files.sort();
+        files.reverse();
+        for (_, path) in files.into_iter().skip(CAPACITY) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The cache key for the raw contents of a log, parsed as `kind`. Another version of the tool may
+/// parse the log differently, so the version is part of the key.
+pub fn key(raw: &[u8], kind: Kind) -> String {
+    format!(
+        "{}-{:016x}-{:?}.json",
+        env!("CARGO_PKG_VERSION"),
+        stable_hash(raw),
+        kind
+    )
+    .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, Entry};
+    use crate::follow::Kind;
+    use crate::warning;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            super::key(b"foo", Kind::Errors),
+            super::key(b"foo", Kind::Errors)
+        );
+        assert_ne!(
+            super::key(b"foo", Kind::Errors),
+            super::key(b"foo", Kind::Warnings)
+        );
+        assert_ne!(
+            super::key(b"foo", Kind::Errors),
+            super::key(b"bar", Kind::Errors)
+        );
+    }
+
+    #[test]
+    fn test_load_store() {
+        let dir = std::env::temp_dir().join(format!("soong-digest-cache-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let key = super::key(b"foo.c:1:2: warning: bar\n", Kind::Warnings);
+        assert!(cache.load(&key).is_none());
+
+        let entry = Entry {
+            items: warning::parse("foo.c:1:2: warning: bar\n  body\n")
+                .unwrap()
+                .collect(),
+            product: Some("walleye".to_string()),
+            partial: false,
+        };
+        cache.store(&key, &entry).unwrap();
+        let loaded = cache.load(&key).unwrap();
+        assert_eq!(loaded.items, entry.items);
+        assert_eq!(loaded.items[0].body, Some("  body".into()));
+        assert_eq!(loaded.product, entry.product);
+
+        // an entry that keeps being used survives pruning, however old it is
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        for i in 0..super::CAPACITY {
+            let key = format!("{}.json", i);
+            cache.store(&key, &entry).unwrap();
+            let file = fs::File::options()
+                .append(true)
+                .open(cache.dir.join(&key))
+                .unwrap();
+            file.set_modified(old).unwrap();
+        }
+        assert!(cache.load(&key).is_some());
+        cache.store("new.json", &entry).unwrap();
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), super::CAPACITY);
+        assert!(cache.load(&key).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
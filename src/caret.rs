@@ -0,0 +1,164 @@
+use crate::item::Span;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Extract the ranges marked by the caret line ("    ^~~~~") under the source line in the body of
+/// a diagnostic on `line`. Each run of '^' and '~' is one span.
+pub fn spans(line: usize, body: &[&str]) -> Vec<Span> {
+    match find(body) {
+        Some((_, carets)) => runs(carets)
+            .map(|(start, end)| Span {
+                start_line: line,
+                start_column: start + 1,
+                end_line: line,
+                end_column: end + 1,
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Find the caret line in the body of a diagnostic. Return its index in `body` and the caret
+/// line itself, without any gutter.
+///
+/// Only the caret line of the diagnostic itself is considered, not those of the notes that
+/// follow it.
+pub fn find<'a>(body: &[&'a str]) -> Option<(usize, &'a str)> {
+    lazy_static! {
+        static ref RE_CARET: Regex = Regex::new(r"^[ ~^]*\^[ ~^]*$").unwrap();
+    }
+    for (i, text) in body.iter().enumerate() {
+        if is_diagnostic(text) {
+            break;
+        }
+        let text = strip_gutter(text).trim_end();
+        if RE_CARET.is_match(text) {
+            return Some((i, text));
+        }
+    }
+    None
+}
+
+/// GCC 9 and later prefix the source and caret lines with "   12 | ".
+pub fn strip_gutter(text: &str) -> &str {
+    lazy_static! {
+        static ref RE_GCC_GUTTER: Regex = Regex::new(r"^\s*\| ").unwrap();
+    }
+    match RE_GCC_GUTTER.find(text) {
+        Some(m) => &text[m.end()..],
+        None => text,
+    }
+}
+
+/// Whether `text` starts a diagnostic, such as "foo.c:1:2: note: ...".
+pub fn is_diagnostic(text: &str) -> bool {
+    lazy_static! {
+        static ref RE_DIAGNOSTIC: Regex = Regex::new(r"^\S+:\d+:(?:\d+:)? \w+: ").unwrap();
+    }
+    RE_DIAGNOSTIC.is_match(text)
+}
+
+/// Whether `text` is the summary clang prints after the diagnostics of a file, such as
+/// "1 warning and 2 errors generated.".
+pub fn is_summary(text: &str) -> bool {
+    lazy_static! {
+        static ref RE_SUMMARY: Regex =
+            Regex::new(r"^\d+ (?:warnings?|errors?)(?: and \d+ errors?)? generated\.$").unwrap();
+    }
+    RE_SUMMARY.is_match(text)
+}
+
+/// The byte ranges of the runs of non-space characters in `s`.
+pub fn runs(s: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut start = None;
+    s.char_indices()
+        .chain(std::iter::once((s.len(), ' ')))
+        .filter_map(move |(i, c)| match (c, start) {
+            (' ', Some(begin)) => {
+                start = None;
+                Some((begin, i))
+            }
+            (' ', None) => None,
+            (_, Some(_)) => None,
+            (_, None) => {
+                start = Some(i);
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item::Span;
+
+    fn span(line: usize, start_column: usize, end_column: usize) -> Span {
+        Span {
+            start_line: line,
+            start_column,
+            end_line: line,
+            end_column,
+        }
+    }
+
+    #[test]
+    fn test_spans_single_caret() {
+        let spans = super::spans(
+            29,
+            &[
+                "        zip->releaseEntry(entry)",
+                "                                ^",
+                "                                ;",
+            ],
+        );
+        assert_eq!(spans, vec![span(29, 33, 34)]);
+    }
+
+    #[test]
+    fn test_spans_range() {
+        let spans = super::spans(
+            2,
+            &[
+                "#include \"does-not-exist.h\"",
+                "         ^~~~~~~~~~~~~~~~~~",
+            ],
+        );
+        assert_eq!(spans, vec![span(2, 10, 28)]);
+    }
+
+    #[test]
+    fn test_spans_multiple_ranges() {
+        let spans = super::spans(7, &["    return a + b;", "           ~ ^ ~"]);
+        assert_eq!(
+            spans,
+            vec![span(7, 12, 13), span(7, 14, 15), span(7, 16, 17)]
+        );
+    }
+
+    #[test]
+    fn test_spans_gcc_gutter() {
+        let spans = super::spans(12, &["   12 |   printf(\"%d\", s);", "      |   ^~~~~~"]);
+        assert_eq!(spans, vec![span(12, 3, 9)]);
+    }
+
+    #[test]
+    fn test_spans_ignore_notes() {
+        let spans = super::spans(
+            40,
+            &[
+                "bionic/libc/include/sys/stat.h:164:5: note: candidate function not viable",
+                "int stat(const char* __path, struct stat* __buf);",
+                "    ^",
+            ],
+        );
+        assert_eq!(spans, vec![]);
+    }
+
+    #[test]
+    fn test_spans_no_caret() {
+        assert_eq!(super::spans(1, &[]), vec![]);
+        assert_eq!(
+            super::spans(1, &["  symbol:   variable NUM_BRUSHES"]),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use crate::digest_error::ParseError;
+use crate::follow::{self, Kind};
+use crate::hash::stable_hash;
+use crate::item::Item;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How far a previous run got into a log. Both the error and the warning parser start from a
+/// clean state at a block boundary, so the offset is all the parser state there is.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Checkpoint<'a> {
+    offset: usize,
+    /// Hash of the log up to `offset`, to tell a log that grew from one that was replaced.
+    hash: u64,
+    items: Vec<Item<'a>>,
+}
+
+/// Parse `contents`, the contents of the log at `path`, starting from where the previous run
+/// left off. The items up to the last block boundary are stored next to the log, in a
+/// .soong-digest directory, for the next run.
+pub fn parse<'a>(path: &Path, contents: &'a str, kind: Kind) -> Result<Vec<Item<'a>>, ParseError> {
+    let file = state_file(path, kind);
+    let mut checkpoint = load(&file)
+        .filter(|c| contents.is_char_boundary(c.offset) && hash(&contents[..c.offset]) == c.hash)
+        .unwrap_or_default();
+
+    let start = checkpoint.offset;
+    let end = start + follow::boundary(&contents[start..], kind);
+    let parse = |region| follow::parse(region, kind).map_err(|e| e.within(contents, region));
+    checkpoint.items.extend(parse(&contents[start..end])?);
+    checkpoint.offset = end;
+    checkpoint.hash = hash(&contents[..end]);
+    // the last block may still grow, so it is parsed again next time
+    let tail = parse(&contents[end..])?;
+
+    if let Err(e) = save(&file, &checkpoint) {
+        eprintln!("{}: failed to save checkpoint: {}", file.display(), e);
+    }
+    let mut items = checkpoint.items;
+    items.extend(tail);
+    Ok(items)
+}
+
+/// The same log may be parsed as different kinds, e.g. verbose.log with --warnings and with
+/// --verbose-log, and each kind finds different items, so each has its own checkpoint.
+fn state_file(log: &Path, kind: Kind) -> PathBuf {
+    let log = fs::canonicalize(log).unwrap_or_else(|_| log.to_path_buf());
+    let name = log
+        .file_name()
+        .map_or("log".into(), |name| name.to_string_lossy());
+    let kind = format!("{:?}", kind).to_lowercase();
+    log.with_file_name(".soong-digest")
+        .join(format!("{}.{}.checkpoint", name, kind))
+}
+
+fn load(file: &Path) -> Option<Checkpoint<'static>> {
+    let contents = fs::read_to_string(file).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn save(file: &Path, checkpoint: &Checkpoint) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, serde_json::to_string(checkpoint)?)
+}
+
+fn hash(s: &str) -> u64 {
+    stable_hash(s.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::follow::Kind;
+    use std::fs;
+
+    #[test]
+    fn test_parse_growing_log() {
+        let dir =
+            std::env::temp_dir().join(format!("soong-digest-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("verbose.log");
+
+        let first =
+            "[1/3] cc foo.c\nfoo.c:1:2: warning: foo\n[2/3] cc bar.c\nbar.c:3:4: warning: bar\n";
+        let items = super::parse(&path, first, Kind::Warnings).unwrap();
+        assert_eq!(items.len(), 2);
+        let file = super::state_file(&path, Kind::Warnings);
+        let mut checkpoint = super::load(&file).unwrap();
+        assert_eq!(checkpoint.offset, 39);
+        assert_eq!(checkpoint.items.len(), 1);
+
+        // the items before the checkpoint are not parsed again
+        checkpoint.items[0].subject = "cached".into();
+        super::save(&file, &checkpoint).unwrap();
+        let second = first.to_string() + "  body\n[3/3] touch baz\n";
+        let items = super::parse(&path, &second, Kind::Warnings).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].subject, "cached");
+        assert_eq!(items[1].subject, "bar");
+        assert_eq!(items[1].body, Some("  body".into()));
+
+        // a log that does not start with the checkpointed data is parsed from scratch
+        let items = super::parse(&path, "foo.c:5:6: warning: baz\n", Kind::Warnings).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "baz");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_as_different_kinds() {
+        let dir = std::env::temp_dir().join(format!(
+            "soong-digest-checkpoint-kinds-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("verbose.log");
+
+        let contents = "[1/3] cc foo.c\nfoo.c:1:2: warning: foo\n\
+                        [2/3] cc bar.c\nFAILED: bar.o\nbar.c:3:4: error: bar\n\
+                        [3/3] cc baz.c\nbaz.c:5:6: warning: baz\n";
+        let items = super::parse(&path, contents, Kind::Warnings).unwrap();
+        assert_eq!(items.len(), 2);
+        // the checkpoint of the warnings has no errors in it
+        let items = super::parse(&path, contents, Kind::Verbose).unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items.iter().any(|item| item.subject == "bar"));
+        let items = super::parse(&path, contents, Kind::Warnings).unwrap();
+        assert_eq!(items.len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use crate::item::{Item, ItemType};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+pub struct Crash {
+    pub command: String,
+    pub reproducers: Vec<String>,
+}
+
+/// Split the output of a FAILED block into the diagnostics printed before the compiler crashed,
+/// and an item describing the crash, if any.
+pub fn split<'a>(output: &'a str, command: &'a str) -> (&'a str, Option<Item<'a>>) {
+    lazy_static! {
+        static ref RE_BUG_REPORT: Regex = Regex::new(r"PLEASE submit a bug report").unwrap();
+        static ref RE_START: Regex =
+            Regex::new(r"(?m)^(?:PLEASE submit a bug report|Stack dump:|\s*#\d+ 0x[0-9a-f]+ )")
+                .unwrap();
+        static ref RE_DRIVER: Regex =
+            Regex::new(r"(?m)^(\S+): error: (clang frontend command failed .*?)\r?$").unwrap();
+        static ref RE_REPRODUCER: Regex =
+            Regex::new(r"(?m)^\S+: note: diagnostic msg: (/\S+?)\r?$").unwrap();
+    }
+    if !RE_BUG_REPORT.is_match(output) {
+        return (output, None);
+    }
+
+    let start = RE_START.find(output).map_or(0, |m| m.start());
+    let (before, report) = output.split_at(start);
+    let (path, subject, body) = match RE_DRIVER.captures(report) {
+        Some(caps) => {
+            let stack = report[..caps.get(0).unwrap().start()].trim_end();
+            (
+                caps.get(1).unwrap().as_str(),
+                caps.get(2).unwrap().as_str(),
+                match stack.len() {
+                    0 => None,
+                    _ => Some(Cow::Borrowed(stack)),
+                },
+            )
+        }
+        None => (program_name(command), "compiler crashed", None),
+    };
+    let reproducers = RE_REPRODUCER
+        .captures_iter(report)
+        .map(|caps| caps.get(1).unwrap().as_str().to_string())
+        .collect();
+    let item = Item {
+        path: Cow::Borrowed(path),
+        subject: Cow::Borrowed(subject),
+        body,
+        type_: ItemType::Crash,
+        crash: Some(Crash {
+            command: command.to_string(),
+            reproducers,
+        }),
+        ..Default::default()
+    };
+    (before, Some(item))
+}
+
+/// The file name of the program run by a command line such as "PWD=/proc/self/cwd
+/// prebuilts/clang/host/linux-x86/clang-r346389b/bin/clang++ -c ...".
+fn program_name(command: &str) -> &str {
+    command
+        .split_whitespace()
+        .find(|word| !word.contains('='))
+        .and_then(|word| word.rsplit('/').next())
+        .unwrap_or("")
+}
+
+/// Copy the reproducer files of all crashes into `dir`, together with a commands.txt listing the
+/// crashing commands. Return the number of files copied.
+pub fn bundle(items: &[Item], dir: &Path) -> std::io::Result<usize> {
+    let crashes = items
+        .iter()
+        .filter_map(|item| item.crash.as_ref())
+        .collect::<Vec<_>>();
+    if crashes.is_empty() {
+        return Ok(0);
+    }
+    fs::create_dir_all(dir)?;
+    let mut commands = fs::File::create(dir.join("commands.txt"))?;
+    let mut total = 0;
+    for crash in crashes {
+        writeln!(commands, "{}", crash.command)?;
+        for path in &crash.reproducers {
+            let path = Path::new(path);
+            match path.file_name() {
+                Some(name) if path.is_file() => {
+                    fs::copy(path, dir.join(name))?;
+                    total += 1;
+                }
+                _ => eprintln!("warning: reproducer {} not found", path.display()),
+            }
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Crash;
+    use crate::item::{Item, ItemType};
+    use std::fs;
+
+    const OUTPUT: &str = "frameworks/base/cmds/idmap/create.cpp:29:33: warning: foo [-Wfoo]\n\
+        Stack dump:\n\
+        0.\tProgram arguments: clang-8 -cc1 -triple x86_64-unknown-linux-android\n\
+        1.\t<eof> parser at end of file\n\
+        clang-8: error: unable to execute command: Segmentation fault (core dumped)\n\
+        clang-8: error: clang frontend command failed due to signal (use -v to see invocation)\n\
+        Android (5220042 based on r346389b) clang version 8.0.7\n\
+        Target: x86_64-unknown-linux-android\n\
+        clang-8: note: diagnostic msg: PLEASE submit a bug report to https://bugs.llvm.org/ and include the crash backtrace, preprocessed source, and associated run script.\n\
+        clang-8: note: diagnostic msg: \n\
+        ********************\n\
+        \n\
+        PLEASE ATTACH THE FOLLOWING FILES TO THE BUG REPORT:\n\
+        Preprocessed source(s) and associated run script(s) are located at:\n\
+        clang-8: note: diagnostic msg: /tmp/create-5d6e0e.cpp\n\
+        clang-8: note: diagnostic msg: /tmp/create-5d6e0e.sh\n\
+        clang-8: note: diagnostic msg: \n\
+        \n\
+        ********************";
+
+    #[test]
+    fn test_split_crash() {
+        let (before, item) = super::split(OUTPUT, "PWD=/proc/self/cwd bin/clang++ -c create.cpp");
+        assert_eq!(
+            before,
+            "frameworks/base/cmds/idmap/create.cpp:29:33: warning: foo [-Wfoo]\n"
+        );
+
+        let item = item.unwrap();
+        assert_eq!(item.type_, ItemType::Crash);
+        assert_eq!(item.path, "clang-8");
+        assert_eq!(
+            item.subject,
+            "clang frontend command failed due to signal (use -v to see invocation)"
+        );
+        assert_eq!(item.body, Some("Stack dump:\n0.\tProgram arguments: clang-8 -cc1 -triple x86_64-unknown-linux-android\n1.\t<eof> parser at end of file\nclang-8: error: unable to execute command: Segmentation fault (core dumped)".into()));
+
+        let crash = item.crash.unwrap();
+        assert_eq!(
+            crash.command,
+            "PWD=/proc/self/cwd bin/clang++ -c create.cpp"
+        );
+        assert_eq!(
+            crash.reproducers,
+            vec!["/tmp/create-5d6e0e.cpp", "/tmp/create-5d6e0e.sh"]
+        );
+    }
+
+    #[test]
+    fn test_split_no_crash() {
+        let (before, item) = super::split("foo.c:1:2: error: bar\n", "clang++ -c foo.c");
+        assert_eq!(before, "foo.c:1:2: error: bar\n");
+        assert!(item.is_none());
+    }
+
+    #[test]
+    fn test_bundle() {
+        let tmp = std::env::temp_dir().join(format!("soong-digest-crash-{}", std::process::id()));
+        let dir = tmp.join("bundle");
+        fs::create_dir_all(&tmp).unwrap();
+        fs::write(tmp.join("create-5d6e0e.cpp"), "int main() {}\n").unwrap();
+        fs::write(
+            tmp.join("create-5d6e0e.sh"),
+            "clang -cc1 create-5d6e0e.cpp\n",
+        )
+        .unwrap();
+        let crash = |command: &str, reproducers: &[&str]| Item {
+            type_: ItemType::Crash,
+            crash: Some(Crash {
+                command: command.to_string(),
+                reproducers: reproducers
+                    .iter()
+                    .map(|name| tmp.join(name).display().to_string())
+                    .collect(),
+            }),
+            ..Default::default()
+        };
+        let items = vec![
+            crash(
+                "clang++ -c create.cpp",
+                &["create-5d6e0e.cpp", "create-5d6e0e.sh"],
+            ),
+            Item::default(),
+            // a missing reproducer is only warned about
+            crash("clang++ -c idmap.cpp", &["idmap-000000.cpp"]),
+        ];
+
+        assert_eq!(super::bundle(&items, &dir).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.join("create-5d6e0e.cpp")).unwrap(),
+            "int main() {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("create-5d6e0e.sh")).unwrap(),
+            "clang -cc1 create-5d6e0e.cpp\n"
+        );
+        assert!(!dir.join("idmap-000000.cpp").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("commands.txt")).unwrap(),
+            "clang++ -c create.cpp\nclang++ -c idmap.cpp\n"
+        );
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_without_crashes() {
+        let dir =
+            std::env::temp_dir().join(format!("soong-digest-no-crash-{}", std::process::id()));
+        assert_eq!(super::bundle(&[Item::default()], &dir).unwrap(), 0);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_program_name() {
+        assert_eq!(
+            super::program_name("PWD=/proc/self/cwd prebuilts/clang/bin/clang++ -c foo.c"),
+            "clang++"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a parser could not make sense of a log. `line` counts from 1, from the start of the text
+/// given to the parser, and is only known when the parser can tell where the error is.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: Option<usize>,
+    /// The line that could not be parsed, if known.
+    pub text: String,
+    pub message: String,
+}
+
+impl ParseError {
+    /// An error somewhere in the log.
+    pub fn new(message: &str) -> ParseError {
+        ParseError {
+            line: None,
+            text: String::new(),
+            message: message.to_string(),
+        }
+    }
+
+    /// An error in the line that contains `text`, which must be a slice of `haystack`.
+    pub fn at(haystack: &str, text: &str, message: &str) -> ParseError {
+        let offset = offset(haystack, text);
+        let start = haystack[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = haystack[offset..]
+            .find('\n')
+            .map_or(haystack.len(), |i| offset + i);
+        ParseError {
+            line: Some(line_number(haystack, offset)),
+            text: haystack[start..end].trim_end_matches('\r').to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// The same error, counting lines from the start of `haystack` instead of `text`, the slice
+    /// of it that was given to the parser.
+    pub fn within(self, haystack: &str, text: &str) -> ParseError {
+        ParseError {
+            line: self
+                .line
+                .map(|line| line + line_number(haystack, offset(haystack, text)) - 1),
+            ..self
+        }
+    }
+}
+
+fn offset(haystack: &str, text: &str) -> usize {
+    text.as_ptr() as usize - haystack.as_ptr() as usize
+}
+
+fn line_number(haystack: &str, offset: usize) -> usize {
+    haystack[..offset].matches('\n').count() + 1
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}: {}", line, self.message, self.text),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Everything that can stop soong-digest from digesting the logs.
+#[derive(Debug)]
+pub enum DigestError {
+    /// An input could not be read or decompressed.
+    Io { path: PathBuf, error: io::Error },
+    /// An input could not be parsed.
+    Parse { path: PathBuf, error: ParseError },
+    /// The digest could not be written.
+    Output(io::Error),
+    /// No input was given, and no logs were found in the output directory, if there is one.
+    NoLogs { out_dir: Option<PathBuf> },
+    /// An input is an invalid glob pattern.
+    Pattern {
+        pattern: String,
+        error: glob::PatternError,
+    },
+    /// An input is a glob pattern that matches no files.
+    NoMatch { pattern: String },
+    /// The build command given to `run` could not be started.
+    Run { command: String, error: io::Error },
+    /// The options given cannot be used together.
+    Usage(&'static str),
+}
+
+impl DigestError {
+    pub fn io(path: impl Into<PathBuf>, error: io::Error) -> DigestError {
+        DigestError::Io {
+            path: path.into(),
+            error,
+        }
+    }
+
+    pub fn parse(path: impl Into<PathBuf>, error: ParseError) -> DigestError {
+        DigestError::Parse {
+            path: path.into(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for DigestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DigestError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            DigestError::Parse { path, error } => match error.line {
+                Some(line) => write!(
+                    f,
+                    "{}:{}: {}\n    {}",
+                    path.display(),
+                    line,
+                    error.message,
+                    error.text
+                ),
+                None => write!(f, "{}: {}", path.display(), error.message),
+            },
+            DigestError::Output(error) => write!(f, "failed to write output: {}", error),
+            DigestError::NoLogs { out_dir: None } => write!(
+                f,
+                "no input given, and neither $OUT_DIR nor $ANDROID_BUILD_TOP is set"
+            ),
+            DigestError::NoLogs {
+                out_dir: Some(out_dir),
+            } => write!(f, "no logs found in {}", out_dir.display()),
+            DigestError::Pattern { pattern, error } => {
+                write!(f, "invalid pattern '{}': {}", pattern, error)
+            }
+            DigestError::NoMatch { pattern } => write!(f, "no files match '{}'", pattern),
+            DigestError::Run { command, error } => {
+                write!(f, "failed to run {}: {}", command, error)
+            }
+            DigestError::Usage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DigestError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{DigestError, ParseError};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_error_at() {
+        let haystack = "[1/2] foo\nfoo.c:99999999999999999999:1: warning: bar\r\n[2/2] baz";
+        let e = ParseError::at(haystack, &haystack[16..], "invalid line number");
+        assert_eq!(e.line, Some(2));
+        assert_eq!(e.text, "foo.c:99999999999999999999:1: warning: bar");
+        assert_eq!(e.within(haystack, &haystack[10..]).line, Some(3));
+
+        let e = ParseError::at(haystack, haystack, "foo");
+        assert_eq!((e.line, e.text.as_str()), (Some(1), "[1/2] foo"));
+        assert_eq!(ParseError::new("foo").within(haystack, haystack).line, None);
+    }
+
+    #[test]
+    fn test_display() {
+        let haystack = "FAILED: foo\nOutput:\n";
+        let e = DigestError::parse(
+            PathBuf::from("out/error.log"),
+            ParseError::at(haystack, &haystack[12..], "failed to parse block"),
+        );
+        assert_eq!(
+            e.to_string(),
+            "out/error.log:2: failed to parse block\n    Output:"
+        );
+
+        let e = DigestError::parse(
+            PathBuf::from("foo.sarif"),
+            ParseError::new("SARIF result without a message"),
+        );
+        assert_eq!(e.to_string(), "foo.sarif: SARIF result without a message");
+    }
+}
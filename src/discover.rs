@@ -0,0 +1,158 @@
+use crate::digest_error::DigestError;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The logs found in the output directory of the last build.
+#[derive(Debug, Default, PartialEq)]
+pub struct Logs {
+    pub errors: Option<PathBuf>,
+    pub verbose_log: Option<PathBuf>,
+}
+
+/// Find the logs of the last build, in $OUT_DIR or $ANDROID_BUILD_TOP/out, as set up by
+/// build/envsetup.sh and lunch.
+pub fn find() -> Result<Logs, DigestError> {
+    let out_dir = env_out_dir().ok_or(DigestError::NoLogs { out_dir: None })?;
+    let logs = find_in(&out_dir);
+    if logs == Logs::default() {
+        return Err(DigestError::NoLogs {
+            out_dir: Some(out_dir),
+        });
+    }
+    Ok(logs)
+}
+
+/// The output directory of the build set up in this shell, if any.
+pub fn env_out_dir() -> Option<PathBuf> {
+    out_dir(env::var_os("OUT_DIR"), env::var_os("ANDROID_BUILD_TOP"))
+}
+
+/// A relative $OUT_DIR is relative to the root of the source tree.
+fn out_dir<S: Into<PathBuf>>(out_dir: Option<S>, build_top: Option<S>) -> Option<PathBuf> {
+    let build_top = build_top.map(Into::into);
+    match (out_dir.map(Into::into), build_top) {
+        (Some(out_dir), Some(build_top)) => Some(build_top.join(out_dir)),
+        (Some(out_dir), None) => Some(out_dir),
+        (None, Some(build_top)) => Some(build_top.join("out")),
+        (None, None) => None,
+    }
+}
+
+fn find_in(out_dir: &Path) -> Logs {
+    let existing = |name: &str| Some(out_dir.join(name)).filter(|path| path.is_file());
+    Logs {
+        errors: existing("error.log"),
+        verbose_log: existing("verbose.log.gz"),
+    }
+}
+
+/// Guess the product a log was built for from the product output directory,
+/// out/target/product/<device>, in `text`: either the path of the log or its contents.
+pub fn product(text: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\btarget/product/([^/\s]+)/").unwrap();
+    }
+    RE.captures(text)
+        .map(|caps| caps.get(1).unwrap().as_str().to_string())
+}
+
+/// Describe a discovered log and how old it is, e.g. "using out/error.log (modified 5 minutes
+/// ago)", so stale logs from an earlier build are easy to spot.
+pub fn describe(path: &Path) -> String {
+    match modified_ago(path) {
+        Some(age) => format!("using {} (modified {})", path.display(), age),
+        None => format!("using {}", path.display()),
+    }
+}
+
+/// How long ago `path` was last modified, e.g. "5 minutes ago".
+pub fn modified_ago(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    let age = SystemTime::now().duration_since(modified).ok()?;
+    Some(format_age(age))
+}
+
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (n, unit) = match seconds {
+        0..=9 => return "just now".to_string(),
+        10..=119 => (seconds, "second"),
+        120..=7199 => (seconds / 60, "minute"),
+        7200..=172_799 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{} {}s ago", n, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Logs;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    #[test]
+    fn test_out_dir() {
+        assert_eq!(super::out_dir::<&str>(None, None), None);
+        assert_eq!(
+            super::out_dir(Some("/out"), None),
+            Some(PathBuf::from("/out"))
+        );
+        assert_eq!(
+            super::out_dir(None, Some("/aosp")),
+            Some(PathBuf::from("/aosp/out"))
+        );
+        assert_eq!(
+            super::out_dir(Some("out-x86"), Some("/aosp")),
+            Some(PathBuf::from("/aosp/out-x86"))
+        );
+        assert_eq!(
+            super::out_dir(Some("/ssd/out"), Some("/aosp")),
+            Some(PathBuf::from("/ssd/out"))
+        );
+    }
+
+    #[test]
+    fn test_find_in() {
+        let dir = Path::new("tests/data/idmap-errors");
+        assert_eq!(
+            super::find_in(dir),
+            Logs {
+                errors: Some(dir.join("error.log")),
+                verbose_log: Some(dir.join("verbose.log.gz")),
+            }
+        );
+        assert_eq!(super::find_in(Path::new("does-not-exist")), Logs::default());
+    }
+
+    #[test]
+    fn test_product() {
+        assert_eq!(
+            super::product("ci/out/target/product/walleye/error.log"),
+            Some("walleye".to_string())
+        );
+        assert_eq!(
+            super::product("[1/2] cp foo out/target/product/generic_x86_64/system/bin/foo"),
+            Some("generic_x86_64".to_string())
+        );
+        assert_eq!(super::product("error.log"), None);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(super::format_age(Duration::from_secs(3)), "just now");
+        assert_eq!(super::format_age(Duration::from_secs(42)), "42 seconds ago");
+        assert_eq!(super::format_age(Duration::from_secs(300)), "5 minutes ago");
+        assert_eq!(
+            super::format_age(Duration::from_secs(3 * 3600)),
+            "3 hours ago"
+        );
+        assert_eq!(
+            super::format_age(Duration::from_secs(5 * 86400)),
+            "5 days ago"
+        );
+    }
+}
@@ -1,16 +1,24 @@
 use crate::ansi::strip_ansi_escape;
-use crate::item::{Item, ItemType};
+use crate::caret;
+use crate::crash;
+use crate::digest_error::ParseError;
+use crate::failure::{self, Failure};
+use crate::fixit;
+use crate::gcc;
+use crate::item::{self, FixIt, Item, ItemType};
+use crate::ninja;
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::borrow::Cow;
 
-pub fn parse<'a, 'b>(haystack: &'a str) -> Result<impl Iterator<Item = Item> + 'b, String> {
+pub fn parse(haystack: &str) -> Result<impl Iterator<Item = Item<'_>>, ParseError> {
     lazy_static! {
-        static ref re: Regex = Regex::new(
+        static ref RE: Regex = Regex::new(
             "(?m)^FAILED: .*\n\
-             Outputs: .*\n\
-             Error: .*\n\
-             Command: .*\n\
-             Output:\n(?s)(.*?)\n\n",
+             Outputs: (.*)\n\
+             Error: (.*)\n\
+             Command: (.*)\n\
+             Output:\n",
         )
         .unwrap();
     }
@@ -18,19 +26,148 @@ pub fn parse<'a, 'b>(haystack: &'a str) -> Result<impl Iterator<Item = Item> + '
     if haystack.is_empty() {
         return Ok(items.into_iter());
     }
-    re.captures_iter(haystack)
-        .try_for_each(|caps| -> Result<(), String> {
-            let mut iter = parse_output(caps.get(1).unwrap().as_str())?;
-            items.extend(&mut iter);
-            Ok(())
-        })?;
+    let headers = RE.captures_iter(haystack).collect::<Vec<_>>();
+    for (i, caps) in headers.iter().enumerate() {
+        let end = headers
+            .get(i + 1)
+            .map_or(haystack.len(), |next| next.get(0).unwrap().start());
+        let region = &haystack[caps.get(0).unwrap().end()..end];
+        parse_block(
+            caps.get(1).unwrap().as_str(),
+            caps.get(2).unwrap().as_str(),
+            caps.get(3).unwrap().as_str(),
+            region,
+            &mut items,
+        );
+    }
+    items.extend(ninja::parse(haystack));
     match items.len() {
-        0 => Err("failed to split input into blocks".to_string()),
+        0 => Err(ParseError::at(
+            haystack,
+            haystack,
+            "failed to split input into blocks",
+        )),
         _ => Ok(items.into_iter()),
     }
 }
 
-fn parse_output<'a, 'b>(haystack: &'a str) -> Result<impl Iterator<Item = Item> + 'b, String> {
+/// Parse the FAILED blocks in verbose.log. Unlike error.log, verbose.log only has the
+/// "FAILED: <outputs>" line, followed directly by the output of the step; the command is the
+/// "[n/m] <command>" line that started the step, and the output runs until ninja prints its next
+/// line.
+pub fn parse_verbose(haystack: &str) -> Result<impl Iterator<Item = Item<'_>>, ParseError> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"(?m)^(?:\[\d+/\d+\] (.*)|FAILED: (.*)|(?:status|verbose|ninja): .*)$")
+                .unwrap();
+    }
+    let mut items = vec![];
+    let mut command = "";
+    let mut block: Option<(&str, usize)> = None;
+    for caps in RE.captures_iter(haystack) {
+        let m = caps.get(0).unwrap();
+        if let Some((outputs, start)) = block.take() {
+            let region = &haystack[start..m.start()];
+            parse_block(outputs, "", command, region, &mut items);
+        }
+        if let Some(c) = caps.get(1) {
+            command = c.as_str();
+        }
+        if let Some(outputs) = caps.get(2) {
+            block = Some((outputs.as_str(), (m.end() + 1).min(haystack.len())));
+        }
+    }
+    if let Some((outputs, start)) = block {
+        let region = &haystack[start..];
+        parse_block(outputs, "", command, region, &mut items);
+    }
+    items.extend(ninja::parse(haystack));
+    Ok(items.into_iter())
+}
+
+fn parse_block<'a>(
+    outputs: &'a str,
+    error: &'a str,
+    command: &'a str,
+    region: &'a str,
+    items: &mut Vec<Item<'a>>,
+) {
+    match strip_ansi_escape(block_output(region)) {
+        Cow::Borrowed(output) => parse_block_output(outputs, error, command, output, items),
+        Cow::Owned(output) => {
+            let mut owned = vec![];
+            parse_block_output(outputs, error, command, &output, &mut owned);
+            items.extend(owned.into_iter().map(|item| item.into_owned()));
+        }
+    }
+}
+
+fn parse_block_output<'a>(
+    outputs: &'a str,
+    error: &'a str,
+    command: &'a str,
+    output: &'a str,
+    items: &mut Vec<Item<'a>>,
+) {
+    let failure = failure::classify(error, output);
+    let (output, crash) = crash::split(output, command);
+    let crashed = crash.is_some();
+    items.extend(crash);
+    match parse_output(output) {
+        Ok(iter) => items.extend(iter.map(|mut item| {
+            item.failure = Some(failure);
+            item
+        })),
+        // the compiler crashed before it reported anything else
+        Err(_) if crashed => {}
+        // the step was killed or starved before it could report anything meaningful
+        Err(_) if failure.is_infrastructure() => {
+            items.push(failure_item(outputs, error, Some(failure)))
+        }
+        // verbose.log does not record how a silent step failed, so it cannot be classified
+        Err(_) if output.trim().is_empty() => items.push(failure_item(outputs, error, None)),
+        // a tool that does not print compiler-style diagnostics, such as a Python script or
+        // aapt2; its output is all there is to report
+        Err(_) => items.push(failure_item(outputs, output, Some(failure))),
+    }
+}
+
+/// The Output section of a FAILED block runs until the next block. Drop the blank lines that
+/// separate the blocks, and anything ninja itself printed after the last block.
+fn block_output(region: &str) -> &str {
+    let mut output = region.trim_end();
+    loop {
+        let start = output.rfind('\n').map_or(0, |i| i + 1);
+        if !output[start..].starts_with("ninja: ") {
+            return output;
+        }
+        output = output[..start].trim_end();
+    }
+}
+
+/// An item for a step that failed without reporting any diagnostic. The subject only depends on
+/// `failure`, so that error.log and verbose.log report the same item; `body` is the "Error:"
+/// line of error.log, or the output of the step if it could not be parsed.
+fn failure_item<'a>(outputs: &'a str, body: &'a str, failure: Option<Failure>) -> Item<'a> {
+    Item {
+        path: Cow::Borrowed(outputs.split_whitespace().next().unwrap_or("")),
+        subject: Cow::Owned(match failure {
+            Some(Failure::Code) => "failed without diagnostics".to_string(),
+            Some(failure) => failure.to_string(),
+            None => "failed without output".to_string(),
+        }),
+        body: match body {
+            "" => None,
+            _ => Some(Cow::Borrowed(body)),
+        },
+        type_: ItemType::Error,
+        failure,
+        ..Default::default()
+    }
+}
+
+/// Parse the output of a step, with any color codes already removed.
+fn parse_output(haystack: &str) -> Result<impl Iterator<Item = Item<'_>>, ParseError> {
     #[derive(Debug)]
     struct InternalItem<'a> {
         path: &'a str,
@@ -38,111 +175,170 @@ fn parse_output<'a, 'b>(haystack: &'a str) -> Result<impl Iterator<Item = Item>
         column: Option<&'a str>,
         subject: &'a str,
         body: Vec<&'a str>,
-    };
+        function: Option<&'a str>,
+        fixits: Vec<FixIt>,
+        type_: ItemType,
+    }
     lazy_static! {
-        static ref re_line_col: Regex =
-            Regex::new(r"^(\S+):(\d+):(\d+): (?:fatal )?error: (.*)").unwrap();
-        static ref re_line_no_col: Regex =
-            Regex::new(r"^(\S+):(\d+): (?:fatal )?error: (.*)").unwrap();
-        static ref re_no_line_no_col: Regex =
-            Regex::new(r"^(\S+): (?:fatal )?error: (.*)").unwrap();
-        static ref re_errors_generated: Regex = Regex::new(r"^\d+ errors? generated\.$").unwrap();
-        static ref re_errors: Regex = Regex::new(r"^\d+ errors?$").unwrap();
+        static ref RE_LINE_COL: Regex =
+            Regex::new(r"^(\S+):(\d+):(\d+): (?:fatal )?(error|warning): (.*)").unwrap();
+        static ref RE_LINE_NO_COL: Regex =
+            Regex::new(r"^(\S+):(\d+): (?:fatal )?(error|warning): (.*)").unwrap();
+        static ref RE_NO_LINE_NO_COL: Regex =
+            Regex::new(r"^(\S+): (?:fatal )?(error|warning): (.*)").unwrap();
+        // notes normally follow the diagnostic they belong to, and become part of its body
+        static ref RE_NOTE: Regex = Regex::new(r"^(\S+?):(\d+):(?:(\d+):)? note: (.*)").unwrap();
+        static ref RE_ERRORS: Regex = Regex::new(r"^\d+ errors?$").unwrap();
     }
     let mut current: Option<InternalItem> = None;
     let mut internal_items = vec![];
-    let haystack = strip_ansi_escape(haystack);
+    let mut context = gcc::Context::default();
     for line in haystack
         .lines()
         .filter(|line| !line.is_empty())
-        .filter(|line| !re_errors_generated.is_match(line))
-        .filter(|line| !re_errors.is_match(line))
+        .filter(|line| !caret::is_summary(line))
+        .filter(|line| !RE_ERRORS.is_match(line))
+        .filter(|line| !gcc::is_noise(line))
     {
-        let caps = re_line_col.captures(&line);
-        if caps.is_some() {
-            let caps = caps.unwrap();
-            if current.is_some() {
-                internal_items.push(current.take().unwrap());
+        if context.update(line) {
+            if let Some(c) = current.take() {
+                internal_items.push(c);
+            }
+            continue;
+        }
+
+        if let Some(caps) = RE_LINE_COL.captures(line) {
+            if let Some(c) = current.take() {
+                internal_items.push(c);
             }
             current = Some(InternalItem {
                 path: caps.get(1).unwrap().as_str(),
                 line: Some(caps.get(2).unwrap().as_str()),
                 column: Some(caps.get(3).unwrap().as_str()),
-                subject: caps.get(4).unwrap().as_str(),
+                subject: caps.get(5).unwrap().as_str(),
                 body: vec![],
+                function: context.function(caps.get(1).unwrap().as_str()),
+                fixits: vec![],
+                type_: item_type(caps.get(4).unwrap().as_str()),
             });
             continue;
         }
 
-        let caps = re_line_no_col.captures(&line);
-        if caps.is_some() {
-            let caps = caps.unwrap();
-            if current.is_some() {
-                internal_items.push(current.take().unwrap());
+        if let Some(caps) = RE_LINE_NO_COL.captures(line) {
+            if let Some(c) = current.take() {
+                internal_items.push(c);
             }
             current = Some(InternalItem {
                 path: caps.get(1).unwrap().as_str(),
                 line: Some(caps.get(2).unwrap().as_str()),
                 column: None,
-                subject: caps.get(3).unwrap().as_str(),
+                subject: caps.get(4).unwrap().as_str(),
                 body: vec![],
+                function: context.function(caps.get(1).unwrap().as_str()),
+                fixits: vec![],
+                type_: item_type(caps.get(3).unwrap().as_str()),
             });
             continue;
         }
 
-        let caps = re_no_line_no_col.captures(&line);
-        if caps.is_some() {
-            let caps = caps.unwrap();
-            if current.is_some() {
-                internal_items.push(current.take().unwrap());
+        if let Some(caps) = RE_NO_LINE_NO_COL.captures(line) {
+            if let Some(c) = current.take() {
+                internal_items.push(c);
             }
             current = Some(InternalItem {
                 path: caps.get(1).unwrap().as_str(),
                 line: None,
                 column: None,
-                subject: caps.get(2).unwrap().as_str(),
+                subject: caps.get(3).unwrap().as_str(),
                 body: vec![],
+                function: context.function(caps.get(1).unwrap().as_str()),
+                fixits: vec![],
+                type_: item_type(caps.get(2).unwrap().as_str()),
             });
             continue;
         }
 
-        if current.is_some() {
-            let mut c = current.unwrap();
-            c.body.push(line);
-            current = Some(c);
+        if current.is_none() {
+            if let Some(caps) = RE_NOTE.captures(line) {
+                current = Some(InternalItem {
+                    path: caps.get(1).unwrap().as_str(),
+                    line: Some(caps.get(2).unwrap().as_str()),
+                    column: caps.get(3).map(|m| m.as_str()),
+                    subject: caps.get(4).unwrap().as_str(),
+                    body: vec![],
+                    function: context.function(caps.get(1).unwrap().as_str()),
+                    fixits: vec![],
+                    type_: ItemType::Note,
+                });
+                continue;
+            }
+        }
+
+        if let Some(c) = current.as_mut() {
+            match fixit::parse(line) {
+                Some(fixit) => c.fixits.push(fixit),
+                None => c.body.push(line),
+            }
             continue;
         }
     }
-    if current.is_some() {
-        internal_items.push(current.take().unwrap());
+    if let Some(c) = current.take() {
+        internal_items.push(c);
     }
 
     let mut out = vec![];
     for ii in internal_items {
+        let invalid_number = || ParseError::at(haystack, ii.path, "invalid line or column number");
+        let line = ii
+            .line
+            .map(|x| x.parse().map_err(|_| invalid_number()))
+            .transpose()?;
+        let column = ii
+            .column
+            .map(|x| x.parse().map_err(|_| invalid_number()))
+            .transpose()?;
         out.push(Item {
-            path: ii.path.to_string(),
-            line: ii.line.map(|x| x.parse().unwrap()),
-            column: ii.column.map(|x| x.parse().unwrap()),
-            subject: ii.subject.to_string(),
-            body: match ii.body.len() {
-                0 => None,
-                _ => Some(ii.body.join("\n")),
+            path: Cow::Borrowed(ii.path),
+            line,
+            column,
+            subject: Cow::Borrowed(ii.subject),
+            body: item::join_lines(haystack, &ii.body),
+            type_: ii.type_,
+            function: ii.function.map(Cow::Borrowed),
+            spans: line.map_or(vec![], |line| caret::spans(line, &ii.body)),
+            fixits: match (ii.fixits.len(), line) {
+                (0, Some(line)) => fixit::from_hint(ii.path, line, &ii.body)
+                    .into_iter()
+                    .collect(),
+                _ => ii.fixits,
             },
-            type_: ItemType::Error,
+            ..Default::default()
         });
     }
     match out.len() {
-        0 => Err(format!("failed to parse block '{}'", &haystack)),
+        0 => Err(ParseError::at(haystack, haystack, "failed to parse block")),
         _ => Ok(out.into_iter()),
     }
 }
 
+fn item_type(severity: &str) -> ItemType {
+    match severity {
+        "warning" => ItemType::Warning,
+        _ => ItemType::Error,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::failure::Failure;
+    use crate::item::{FixIt, ItemType, Span};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
     #[test]
     fn test_parse_java_errors() {
         let haystack = include_str!("../tests/data/easter-egg-errors-java/error.log");
-        let items = super::parse(&haystack).unwrap().collect::<Vec<_>>();
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
         assert_eq!(items.len(), 3);
 
         let i = &items[0];
@@ -153,7 +349,7 @@ mod tests {
         assert_eq!(i.line, Some(228));
         assert_eq!(i.column, None);
         assert_eq!(i.subject, "cannot find symbol");
-        assert_eq!(i.body, Some("            for (int i = 0; i < NUM_BRUSHES; i++) {\n                                ^\n  symbol:   variable NUM_BRUSHES\n  location: class PaintActivity".to_string()));
+        assert_eq!(i.body, Some("            for (int i = 0; i < NUM_BRUSHES; i++) {\n                                ^\n  symbol:   variable NUM_BRUSHES\n  location: class PaintActivity".into()));
 
         let i = &items[1];
         assert_eq!(
@@ -163,7 +359,7 @@ mod tests {
         assert_eq!(i.line, Some(233));
         assert_eq!(i.column, None);
         assert_eq!(i.subject, "cannot find symbol");
-        assert_eq!(i.body, Some("                        (float) Math.pow((float) i / NUM_BRUSHES, 2f), minBrushWidth,\n                                                     ^\n  symbol:   variable NUM_BRUSHES\n  location: class PaintActivity".to_string()));
+        assert_eq!(i.body, Some("                        (float) Math.pow((float) i / NUM_BRUSHES, 2f), minBrushWidth,\n                                                     ^\n  symbol:   variable NUM_BRUSHES\n  location: class PaintActivity".into()));
 
         let i = &items[2];
         assert_eq!(
@@ -173,13 +369,13 @@ mod tests {
         assert_eq!(i.line, Some(311));
         assert_eq!(i.column, None);
         assert_eq!(i.subject, "cannot find symbol");
-        assert_eq!(i.body, Some("        thisDoesNotExist();\n        ^\n  symbol:   method thisDoesNotExist()\n  location: class PaintActivity".to_string()));
+        assert_eq!(i.body, Some("        thisDoesNotExist();\n        ^\n  symbol:   method thisDoesNotExist()\n  location: class PaintActivity".into()));
     }
 
     #[test]
     fn test_parse_kotlin_errors() {
         let haystack = include_str!("../tests/data/easter-egg-errors-kt/error.log");
-        let items = super::parse(&haystack).unwrap().collect::<Vec<_>>();
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
         assert_eq!(items.len(), 3);
 
         let i = &items[0];
@@ -190,10 +386,7 @@ mod tests {
         assert_eq!(i.line, Some(48));
         assert_eq!(i.column, Some(9));
         assert_eq!(i.subject, "unresolved reference: thisDoesNotExist");
-        assert_eq!(
-            i.body,
-            Some("        thisDoesNotExist()\n        ^".to_string())
-        );
+        assert_eq!(i.body, Some("        thisDoesNotExist()\n        ^".into()));
 
         let i = &items[1];
         assert_eq!(
@@ -210,7 +403,7 @@ mod tests {
             i.body,
             Some(
                 "                    cutoutRight = r.width()\n                                  ^"
-                    .to_string()
+                    .into()
             )
         );
 
@@ -225,13 +418,13 @@ mod tests {
             i.subject,
             "none of the following functions can be called with the arguments supplied: "
         );
-        assert_eq!(i.body, Some("public constructor LayoutParams(c: Context!, attrs: AttributeSet!) defined in android.widget.LinearLayout.LayoutParams\npublic constructor LayoutParams(width: Int, height: Int) defined in android.widget.LinearLayout.LayoutParams\n                it.layoutParams = LayoutParams(cutoutRight, MATCH_PARENT)\n                                  ^".to_string()));
+        assert_eq!(i.body, Some("public constructor LayoutParams(c: Context!, attrs: AttributeSet!) defined in android.widget.LinearLayout.LayoutParams\npublic constructor LayoutParams(width: Int, height: Int) defined in android.widget.LinearLayout.LayoutParams\n                it.layoutParams = LayoutParams(cutoutRight, MATCH_PARENT)\n                                  ^".into()));
     }
 
     #[test]
     fn test_parse_cpp_errors() {
         let haystack = include_str!("../tests/data/idmap-errors/error.log");
-        let items = super::parse(&haystack).unwrap().collect::<Vec<_>>();
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
         assert_eq!(items.len(), 3);
 
         let i = &items[0];
@@ -242,27 +435,41 @@ mod tests {
             i.subject,
             "control may reach end of non-void function [-Werror,-Wreturn-type]"
         );
-        assert_eq!(i.body, Some("    }\n    ^".to_string()));
+        assert_eq!(i.body, Some("    }\n    ^".into()));
 
         let i = &items[1];
         assert_eq!(i.path, "frameworks/base/cmds/idmap/create.cpp");
         assert_eq!(i.line, Some(29));
         assert_eq!(i.column, Some(33));
         assert_eq!(i.subject, "expected ';' after expression");
-        assert_eq!(i.body, Some("        zip->releaseEntry(entry)\n                                ^\n                                ;".to_string()));
+        assert_eq!(i.body, Some("        zip->releaseEntry(entry)\n                                ^\n                                ;".into()));
+        assert_eq!(
+            i.fixits,
+            vec![FixIt {
+                path: "frameworks/base/cmds/idmap/create.cpp".to_string(),
+                span: Span {
+                    start_line: 29,
+                    start_column: 33,
+                    end_line: 29,
+                    end_column: 33,
+                },
+                replacement: ";".to_string(),
+            }]
+        );
 
         let i = &items[2];
         assert_eq!(i.path, "frameworks/base/cmds/idmap/create.cpp");
         assert_eq!(i.line, Some(89));
         assert_eq!(i.column, Some(13));
         assert_eq!(i.subject, "no matching function for call to 'lseek'");
-        assert_eq!(i.body, Some("        if (lseek(idmap_fd, 0) < 0) {\n            ^~~~~\nbionic/libc/include/unistd.h:258:7: note: candidate function not viable: requires 3 arguments, but 2 were provided\noff_t lseek(int __fd, off_t __offset, int __whence);\n      ^".to_string()));
+        assert_eq!(i.fixits, vec![]);
+        assert_eq!(i.body, Some("        if (lseek(idmap_fd, 0) < 0) {\n            ^~~~~\nbionic/libc/include/unistd.h:258:7: note: candidate function not viable: requires 3 arguments, but 2 were provided\noff_t lseek(int __fd, off_t __offset, int __whence);\n      ^".into()));
     }
 
     #[test]
     fn test_parse_cpp_fatal_errors() {
         let haystack = include_str!("../tests/data/idmap-fatal-errors/error.log");
-        let items = super::parse(&haystack).unwrap().collect::<Vec<_>>();
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
         assert_eq!(items.len(), 1);
 
         let i = &items[0];
@@ -272,14 +479,23 @@ mod tests {
         assert_eq!(i.subject, "'does-not-exist.h' file not found");
         assert_eq!(
             i.body,
-            Some("#include \"does-not-exist.h\"\n         ^~~~~~~~~~~~~~~~~~".to_string())
+            Some("#include \"does-not-exist.h\"\n         ^~~~~~~~~~~~~~~~~~".into())
+        );
+        assert_eq!(
+            i.spans,
+            vec![Span {
+                start_line: 2,
+                start_column: 10,
+                end_line: 2,
+                end_column: 28,
+            }]
         );
     }
 
     #[test]
     fn test_parse_cpp_linker_errors() {
         let haystack = include_str!("../tests/data/idmap-linker-errors/error.log");
-        let items = super::parse(&haystack).unwrap().collect::<Vec<_>>();
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
         assert_eq!(items.len(), 2);
 
         let i = &items[0];
@@ -292,7 +508,7 @@ mod tests {
         );
         assert_eq!(
             i.body,
-            Some(">>> referenced by idmap.cpp:150 (frameworks/base/cmds/idmap/idmap.cpp:150)\n>>>               out/soong/.intermediates/frameworks/base/cmds/idmap/idmap/android_x86_64_core/obj/frameworks/base/cmds/idmap/idmap.o:(main)".to_string())
+            Some(">>> referenced by idmap.cpp:150 (frameworks/base/cmds/idmap/idmap.cpp:150)\n>>>               out/soong/.intermediates/frameworks/base/cmds/idmap/idmap/android_x86_64_core/obj/frameworks/base/cmds/idmap/idmap.o:(main)".into())
         );
 
         let i = &items[1];
@@ -307,7 +523,211 @@ mod tests {
     }
 
     #[test]
-    fn test_failure_to_parse_a_block() {
+    fn test_parse_ninja_errors() {
+        let haystack = "ninja: error: 'out/foo.o', needed by 'out/bar', missing and no known rule to make it\n\
+                        ninja: build stopped: subcommand failed.\n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+
+        let i = &items[0];
+        assert_eq!(i.path, "ninja");
+        assert_eq!(
+            i.subject,
+            "'out/foo.o', needed by 'out/bar', missing and no known rule to make it"
+        );
+    }
+
+    #[test]
+    fn test_parse_classifies_code_errors() {
+        let haystack = include_str!("../tests/data/idmap-fatal-errors/error.log");
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].failure, Some(Failure::Code));
+    }
+
+    #[test]
+    fn test_parse_infrastructure_failure() {
+        let haystack = "FAILED: //foo:foo clang++ foo.cpp\n\
+                        Outputs: out/foo.o\n\
+                        Error: exited with code: 137\n\
+                        Command: some command\n\
+                        Output:\n\
+                        \n\
+                        FAILED: //foo:bar clang++ bar.cpp\n\
+                        Outputs: out/bar.o\n\
+                        Error: exited with code: 1\n\
+                        Command: some command\n\
+                        Output:\n\
+                        clang++: error: unable to open output file 'out/bar.o': 'No space left on device'\n\
+                        \n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+
+        let i = &items[0];
+        assert_eq!(i.path, "out/foo.o");
+        assert_eq!(i.subject, "out of memory");
+        assert_eq!(i.body, Some("exited with code: 137".into()));
+        assert_eq!(i.failure, Some(Failure::OutOfMemory));
+
+        let i = &items[1];
+        assert_eq!(i.path, "clang++");
+        assert_eq!(i.failure, Some(Failure::DiskFull));
+    }
+
+    #[test]
+    fn test_parse_compiler_crash() {
+        let haystack = "FAILED: //foo:foo clang++ foo.cpp\n\
+                        Outputs: out/foo.o\n\
+                        Error: exited with code: 1\n\
+                        Command: PWD=/proc/self/cwd bin/clang++ -c foo.cpp\n\
+                        Output:\n\
+                        foo.cpp:1:2: error: bar\n\
+                        Stack dump:\n\
+                        0.\tProgram arguments: clang-8 -cc1\n\
+                        clang-8: error: clang frontend command failed due to signal (use -v to see invocation)\n\
+                        clang-8: note: diagnostic msg: PLEASE submit a bug report to https://bugs.llvm.org/ and include the crash backtrace, preprocessed source, and associated run script.\n\
+                        clang-8: note: diagnostic msg: \n\
+                        ********************\n\
+                        \n\
+                        PLEASE ATTACH THE FOLLOWING FILES TO THE BUG REPORT:\n\
+                        Preprocessed source(s) and associated run script(s) are located at:\n\
+                        clang-8: note: diagnostic msg: /tmp/foo-5d6e0e.cpp\n\
+                        clang-8: note: diagnostic msg: /tmp/foo-5d6e0e.sh\n\
+                        clang-8: note: diagnostic msg: \n\
+                        \n\
+                        ********************\n\
+                        \n\
+                        ninja: build stopped: subcommand failed.\n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+
+        let i = &items[0];
+        assert_eq!(i.type_, ItemType::Crash);
+        assert_eq!(i.path, "clang-8");
+        let crash = i.crash.as_ref().unwrap();
+        assert_eq!(crash.command, "PWD=/proc/self/cwd bin/clang++ -c foo.cpp");
+        assert_eq!(
+            crash.reproducers,
+            vec!["/tmp/foo-5d6e0e.cpp", "/tmp/foo-5d6e0e.sh"]
+        );
+
+        let i = &items[1];
+        assert_eq!(i.type_, ItemType::Error);
+        assert_eq!(i.path, "foo.cpp");
+        assert_eq!(i.subject, "bar");
+        assert_eq!(i.body, None);
+    }
+
+    #[test]
+    fn test_parse_gcc_errors() {
+        let haystack = "FAILED: //foo:foo gcc foo.c\n\
+                        Outputs: out/foo.o\n\
+                        Error: exited with code: 1\n\
+                        Command: gcc -c foo.c\n\
+                        Output:\n\
+                        foo.c: In function ‘main’:\n\
+                        foo.c:12:3: error: format ‘%d’ expects argument of type ‘int’ [-Werror=format=]\n   \
+                        12 |   printf(\"%d\", s);\n      \
+                        |   ^~~~~~\n\
+                        foo.c: At top level:\n\
+                        foo.c:20:1: error: expected ‘;’ before ‘}’ token\n\
+                        cc1: all warnings being treated as errors\n\
+                        \n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+
+        let i = &items[0];
+        assert_eq!(i.path, "foo.c");
+        assert_eq!(i.line, Some(12));
+        assert_eq!(i.column, Some(3));
+        assert_eq!(
+            i.subject,
+            "format ‘%d’ expects argument of type ‘int’ [-Werror=format=]"
+        );
+        assert_eq!(
+            i.body,
+            Some("   12 |   printf(\"%d\", s);\n      |   ^~~~~~".into())
+        );
+        assert_eq!(i.function, Some("main".into()));
+
+        let i = &items[1];
+        assert_eq!(i.line, Some(20));
+        assert_eq!(i.body, None);
+        assert_eq!(i.function, None);
+    }
+
+    #[test]
+    fn test_parse_warnings_and_notes() {
+        let haystack = "FAILED: //foo:foo clang++ foo.cpp\n\
+                        Outputs: out/foo.o\n\
+                        Error: exited with code: 1\n\
+                        Command: clang++ -c foo.cpp\n\
+                        Output:\n\
+                        foo.h:3:1: note: in file included from foo.cpp:1\n\
+                        foo.cpp:5:9: warning: unused variable 'x' [-Wunused-variable]\n    \
+                        int x;\n        \
+                        ^\n\
+                        foo.cpp:7:5: error: use of undeclared identifier 'y'\n\
+                        foo.cpp:2:5: note: did you mean 'z'?\n\
+                        1 warning and 1 error generated.\n\
+                        \n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+
+        let i = &items[0];
+        assert_eq!(i.type_, ItemType::Note);
+        assert_eq!(i.path, "foo.h");
+        assert_eq!(i.subject, "in file included from foo.cpp:1");
+
+        let i = &items[1];
+        assert_eq!(i.type_, ItemType::Warning);
+        assert_eq!(i.subject, "unused variable 'x' [-Wunused-variable]");
+        assert_eq!(i.body, Some("    int x;\n        ^".into()));
+        assert_eq!(i.failure, Some(Failure::Code));
+
+        let i = &items[2];
+        assert_eq!(i.type_, ItemType::Error);
+        assert_eq!(i.body, Some("foo.cpp:2:5: note: did you mean 'z'?".into()));
+    }
+
+    #[test]
+    fn test_parse_parseable_fixits() {
+        let haystack = "FAILED: //foo:foo clang++ foo.cpp\n\
+                        Outputs: out/foo.o\n\
+                        Error: exited with code: 1\n\
+                        Command: clang++ -fdiagnostics-parseable-fixits -c foo.cpp\n\
+                        Output:\n\
+                        foo.cpp:3:5: error: use of undeclared identifier 'lseeek'; did you mean 'lseek'?\n    \
+                        lseeek(fd, 0, 0);\n    \
+                        ^~~~~~\n    \
+                        lseek\n\
+                        fix-it:\"foo.cpp\":{3:5-3:11}:\"lseek\"\n\
+                        \n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+
+        let i = &items[0];
+        assert_eq!(
+            i.body,
+            Some("    lseeek(fd, 0, 0);\n    ^~~~~~\n    lseek".into())
+        );
+        assert_eq!(
+            i.fixits,
+            vec![FixIt {
+                path: "foo.cpp".to_string(),
+                span: Span {
+                    start_line: 3,
+                    start_column: 5,
+                    end_line: 3,
+                    end_column: 11,
+                },
+                replacement: "lseek".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unparsed_block() {
         let haystack = "FAILED: some path\n\
                         Outputs: some object\n\
                         Error: some return value\n\
@@ -316,26 +736,155 @@ mod tests {
                         some output not recognized by the parser\n\
                         \n\
                         \n";
-        let result = super::parse(&haystack);
-        assert!(result.is_err());
-        assert!(result.err().unwrap().contains("failed to parse block"));
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, "some");
+        assert_eq!(items[0].subject, "failed without diagnostics");
+        assert_eq!(
+            items[0].body.as_deref(),
+            Some("some output not recognized by the parser")
+        );
+    }
+
+    #[test]
+    fn test_unparsed_line_number() {
+        let haystack = "FAILED: foo.o\n\
+                        Outputs: foo.o\n\
+                        Error: exited with code: 1\n\
+                        Command: clang foo.c\n\
+                        Output:\n\
+                        foo.c:99999999999999999999:1: error: bar\n";
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "failed without diagnostics");
+        assert_eq!(
+            items[0].body.as_deref(),
+            Some("foo.c:99999999999999999999:1: error: bar")
+        );
     }
 
     #[test]
     fn test_failure_to_parse_anything() {
         let haystack = "foo";
-        let result = super::parse(&haystack);
+        let result = super::parse(haystack);
         assert!(result.is_err());
         assert!(result
             .err()
             .unwrap()
+            .message
             .contains("failed to split input into blocks"));
     }
 
     #[test]
     fn test_empty_input_is_ok() {
         let haystack = "";
-        let items = super::parse(&haystack).unwrap();
+        let items = super::parse(haystack).unwrap();
+        assert_eq!(items.count(), 0);
+    }
+
+    fn uncompress(raw: &[u8]) -> String {
+        let mut decoder = GzDecoder::new(raw);
+        let mut contents = String::new();
+        decoder.read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_parse_verbose_matches_error_log() {
+        let verbose = uncompress(include_bytes!("../tests/data/idmap-errors/verbose.log.gz"));
+        let verbose = super::parse_verbose(&verbose).unwrap().collect::<Vec<_>>();
+        let errors = include_str!("../tests/data/idmap-errors/error.log");
+        let errors = super::parse(errors).unwrap().collect::<Vec<_>>();
+        assert_eq!(verbose.len(), 3);
+        assert_eq!(verbose, errors);
+        assert_eq!(verbose[1].body, errors[1].body);
+        assert_eq!(verbose[1].fixits, errors[1].fixits);
+    }
+
+    #[test]
+    fn test_parse_verbose_java_errors() {
+        let verbose = uncompress(include_bytes!(
+            "../tests/data/easter-egg-errors-java/verbose.log.gz"
+        ));
+        let items = super::parse_verbose(&verbose).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[2].body, Some("        thisDoesNotExist();\n        ^\n  symbol:   method thisDoesNotExist()\n  location: class PaintActivity".into()));
+    }
+
+    #[test]
+    fn test_parse_verbose_blocks() {
+        let haystack = "[1/3] clang -c foo.c\n\
+                        FAILED: foo.o\n\
+                        foo.c:1:2: error: bar\n\
+                        [2/3] java -Xmx1g -jar d8.jar\n\
+                        FAILED: classes.dex\n\
+                        java.lang.OutOfMemoryError: Java heap space\n\
+                        \n\
+                        [3/3] touch baz\n\
+                        ninja: build stopped: subcommand failed.\n";
+        let items = super::parse_verbose(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].path, "foo.c");
+        assert_eq!(items[0].body, None);
+        assert_eq!(items[0].failure, Some(Failure::Code));
+        assert_eq!(items[1].path, "classes.dex");
+        assert_eq!(items[1].subject, "out of memory");
+        assert_eq!(items[1].failure, Some(Failure::OutOfMemory));
+    }
+
+    #[test]
+    fn test_parse_verbose_silent_failure() {
+        let haystack = "[1/2] clang -c foo.c\n\
+                        FAILED: foo.o\n\
+                        [2/2] touch bar\n\
+                        ninja: build stopped: subcommand failed.\n";
+        let items = super::parse_verbose(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, "foo.o");
+        assert_eq!(items[0].subject, "failed without output");
+        assert_eq!(items[0].failure, None);
+    }
+
+    #[test]
+    fn test_parse_verbose_tool_failure() {
+        let haystack = "[1/2] python3 gen.py\n\
+                        FAILED: gen/foo.h\n\
+                        Traceback (most recent call last):\n  \
+                        File \"gen.py\", line 3, in <module>\n    \
+                        import yaml\n\
+                        ModuleNotFoundError: No module named 'yaml'\n\
+                        [2/2] touch bar\n\
+                        ninja: build stopped: subcommand failed.\n";
+        let items = super::parse_verbose(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, "gen/foo.h");
+        assert_eq!(items[0].subject, "failed without diagnostics");
+        assert_eq!(items[0].failure, Some(Failure::Code));
+        let body = items[0].body.as_ref().unwrap();
+        assert!(body.starts_with("Traceback (most recent call last):\n"));
+        assert!(body.ends_with("ModuleNotFoundError: No module named 'yaml'"));
+    }
+
+    #[test]
+    fn test_parse_verbose_matches_error_log_infrastructure_failure() {
+        let errors = "FAILED: //foo:foo clang++ foo.cpp\n\
+                      Outputs: out/foo.o\n\
+                      Error: exited with code: 1\n\
+                      Command: clang++ foo.cpp\n\
+                      Output:\n\
+                      java.lang.OutOfMemoryError: Java heap space\n";
+        let verbose = "[1/1] clang++ foo.cpp\n\
+                       FAILED: out/foo.o\n\
+                       java.lang.OutOfMemoryError: Java heap space\n";
+        let errors = super::parse(errors).unwrap().collect::<Vec<_>>();
+        let verbose = super::parse_verbose(verbose).unwrap().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors, verbose);
+    }
+
+    #[test]
+    fn test_parse_verbose_without_failures() {
+        let items = super::parse_verbose("[1/1] touch foo\n").unwrap();
         assert_eq!(items.count(), 0);
     }
 }
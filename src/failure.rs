@@ -0,0 +1,151 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a build step failed.
+///
+/// Anything but `Code` is a problem with the machine running the build rather than with the
+/// code being built, and retrying the build may succeed.
+#[derive(Clone, Copy, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+pub enum Failure {
+    Code,
+    OutOfMemory,
+    Signal(u32),
+    DiskFull,
+    Timeout,
+}
+
+impl Failure {
+    pub fn is_infrastructure(self) -> bool {
+        self != Failure::Code
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Code => write!(f, "code"),
+            Failure::OutOfMemory => write!(f, "out of memory"),
+            Failure::Signal(n) => write!(f, "killed by signal {}", n),
+            Failure::DiskFull => write!(f, "disk full"),
+            Failure::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+/// Classify a FAILED block based on its "Error:" line and its output.
+pub fn classify(error: &str, output: &str) -> Failure {
+    lazy_static! {
+        static ref RE_EXIT_CODE: Regex = Regex::new(r"exited with code: (\d+)").unwrap();
+        static ref RE_SIGNAL: Regex =
+            Regex::new(r"(?:killed|terminated) by signal:? (\d+)|signal: killed").unwrap();
+        static ref RE_DISK_FULL: Regex = Regex::new(r"No space left on device").unwrap();
+        static ref RE_OUT_OF_MEMORY: Regex = Regex::new(
+            r"(?m)^Killed$|[Oo]ut of memory|Cannot allocate memory|std::bad_alloc|java\.lang\.OutOfMemoryError"
+        )
+        .unwrap();
+        static ref RE_TIMEOUT: Regex = Regex::new(r"(?i)timed out|timeout").unwrap();
+    }
+
+    if RE_DISK_FULL.is_match(output) {
+        return Failure::DiskFull;
+    }
+    if RE_OUT_OF_MEMORY.is_match(output) {
+        return Failure::OutOfMemory;
+    }
+    if RE_TIMEOUT.is_match(error) {
+        return Failure::Timeout;
+    }
+
+    let signal = if let Some(caps) = RE_SIGNAL.captures(error) {
+        match caps.get(1) {
+            // a number too large to be a signal is not one
+            Some(m) => m.as_str().parse().ok(),
+            // "signal: killed" carries no number
+            None => Some(9),
+        }
+    } else if let Some(caps) = RE_EXIT_CODE.captures(error) {
+        // shells report a child killed by signal N as exit code 128 + N, and timeout(1) exits
+        // with 124
+        match caps.get(1).unwrap().as_str().parse::<u32>() {
+            Ok(124) => return Failure::Timeout,
+            Ok(code) if code > 128 && code < 128 + 64 => Some(code - 128),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    match signal {
+        // the OOM killer sends SIGKILL and leaves no other trace in the output
+        Some(9) if output.trim().is_empty() => Failure::OutOfMemory,
+        Some(n) => Failure::Signal(n),
+        None => Failure::Code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, Failure};
+
+    #[test]
+    fn test_classify_code() {
+        assert_eq!(
+            classify("exited with code: 1", "foo.c:1:2: error: bar"),
+            Failure::Code
+        );
+        assert!(!Failure::Code.is_infrastructure());
+    }
+
+    #[test]
+    fn test_classify_disk_full() {
+        assert_eq!(
+            classify(
+                "exited with code: 1",
+                "ld.lld: error: failed to write to the output file: No space left on device"
+            ),
+            Failure::DiskFull
+        );
+    }
+
+    #[test]
+    fn test_classify_out_of_memory() {
+        assert_eq!(classify("exited with code: 137", ""), Failure::OutOfMemory);
+        assert_eq!(
+            classify(
+                "exited with code: 1",
+                "java.lang.OutOfMemoryError: Java heap space"
+            ),
+            Failure::OutOfMemory
+        );
+        assert_eq!(
+            classify("exited with code: 137", "Killed"),
+            Failure::OutOfMemory
+        );
+        assert!(Failure::OutOfMemory.is_infrastructure());
+    }
+
+    #[test]
+    fn test_classify_signal() {
+        assert_eq!(
+            classify("exited with code: 139", "some output"),
+            Failure::Signal(11)
+        );
+        assert_eq!(classify("terminated by signal 15", ""), Failure::Signal(15));
+        assert_eq!(
+            classify("signal: killed", "some output"),
+            Failure::Signal(9)
+        );
+        assert_eq!(
+            classify("killed by signal 99999999999", "some output"),
+            Failure::Code
+        );
+    }
+
+    #[test]
+    fn test_classify_timeout() {
+        assert_eq!(classify("exited with code: 124", ""), Failure::Timeout);
+        assert_eq!(classify("command timed out", ""), Failure::Timeout);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::caret;
+use crate::digest_error::DigestError;
+use crate::item::{FixIt, Span};
+use lazy_static::lazy_static;
+use regex::Regex;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Parse a fix-it in the format printed by clang's -fdiagnostics-parseable-fixits:
+/// fix-it:"foo.c":{29:33-29:33}:";"
+pub fn parse(line: &str) -> Option<FixIt> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r#"^fix-it:"((?:[^"\\]|\\.)*)":\{(\d+):(\d+)-(\d+):(\d+)\}:"((?:[^"\\]|\\.)*)"$"#
+        )
+        .unwrap();
+    }
+    if !line.starts_with("fix-it:") {
+        return None;
+    }
+    let caps = RE.captures(line)?;
+    Some(FixIt {
+        path: unescape(caps.get(1).unwrap().as_str()),
+        span: Span {
+            start_line: caps.get(2).unwrap().as_str().parse().ok()?,
+            start_column: caps.get(3).unwrap().as_str().parse().ok()?,
+            end_line: caps.get(4).unwrap().as_str().parse().ok()?,
+            end_column: caps.get(5).unwrap().as_str().parse().ok()?,
+        },
+        replacement: unescape(caps.get(6).unwrap().as_str()),
+    })
+}
+
+/// Extract the fix-it hint clang and GCC print below the caret line, lined up with the range it
+/// replaces:
+///
+/// ```text
+///     zip->releaseEntry(entry)
+///                             ^
+///                             ;
+/// ```
+///
+/// A lone caret marks an insertion point; a range marked with '~' is replaced.
+///
+/// The hint must be the last line of the diagnostic's own body, so that GCC's range labels
+/// ("|" under the range, with the label below it) aren't taken for a hint.
+pub fn from_hint(path: &str, line: usize, body: &[&str]) -> Option<FixIt> {
+    let (i, carets) = caret::find(body)?;
+    let hint = body.get(i + 1)?;
+    if caret::is_diagnostic(hint) || caret::is_summary(hint) {
+        return None;
+    }
+    if let Some(text) = body[i + 2..].iter().find(|text| !caret::is_summary(text)) {
+        if !caret::is_diagnostic(text) {
+            return None;
+        }
+    }
+    let hint = caret::strip_gutter(hint).trim_end();
+    if hint.trim_start().starts_with('|') {
+        return None;
+    }
+    let column = hint.len() - hint.trim_start().len();
+    let (start, end) = caret::runs(carets).find(|(start, _)| *start == column)?;
+    let end = match &carets[start..end] {
+        "^" => start,
+        _ => end,
+    };
+    Some(FixIt {
+        path: path.to_string(),
+        span: Span {
+            start_line: line,
+            start_column: start + 1,
+            end_line: line,
+            end_column: end + 1,
+        },
+        replacement: hint.trim_start().to_string(),
+    })
+}
+
+/// Undo the escaping done by LLVM's raw_ostream::write_escaped.
+fn unescape(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes().peekable();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => out.push(b'\n'),
+            Some(b't') => out.push(b'\t'),
+            Some(c @ b'0'..=b'7') => {
+                let mut value = u32::from(c - b'0');
+                for _ in 0..2 {
+                    match bytes.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(d - b'0');
+                            bytes.next();
+                        }
+                        _ => break,
+                    }
+                }
+                out.push(value as u8);
+            }
+            Some(c) => out.push(c),
+            None => out.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Apply fix-its to the files they refer to, relative to `root`. If `dry_run` is set, write a
+/// unified diff of the changes to `out` instead. Return the number of fix-its applied.
+pub fn apply<W: Write>(
+    fixits: &[FixIt],
+    root: &Path,
+    dry_run: bool,
+    out: &mut W,
+) -> Result<usize, DigestError> {
+    let mut by_path: BTreeMap<&str, Vec<&FixIt>> = BTreeMap::new();
+    for fixit in fixits {
+        by_path.entry(&fixit.path).or_default().push(fixit);
+    }
+    let mut total = 0;
+    for (path, mut fixits) in by_path {
+        fixits.sort();
+        fixits.dedup();
+        let full_path = root.join(path);
+        let old = fs::read_to_string(&full_path).map_err(|e| DigestError::io(&full_path, e))?;
+        let (new, n) = apply_to(&old, &fixits);
+        total += n;
+        if dry_run {
+            let diff = TextDiff::from_lines(&old, &new);
+            write!(
+                out,
+                "{}",
+                diff.unified_diff()
+                    .header(&format!("a/{}", path), &format!("b/{}", path))
+            )
+            .map_err(DigestError::Output)?;
+        } else if n > 0 {
+            fs::write(&full_path, new).map_err(|e| DigestError::io(&full_path, e))?;
+        }
+    }
+    Ok(total)
+}
+
+fn apply_to(text: &str, fixits: &[&FixIt]) -> (String, usize) {
+    let line_starts = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect::<Vec<_>>();
+    let offset = |line: usize, column: usize| -> Option<usize> {
+        let start = *line_starts.get(line.checked_sub(1)?)?;
+        if column == Span::END_OF_LINE {
+            return Some(text[start..].find('\n').map_or(text.len(), |i| start + i));
+        }
+        let offset = start + column.checked_sub(1)?;
+        match text.is_char_boundary(offset) {
+            true => Some(offset),
+            false => None,
+        }
+    };
+    let mut edits = fixits
+        .iter()
+        .filter_map(|fixit| {
+            let start = offset(fixit.span.start_line, fixit.span.start_column)?;
+            let end = offset(fixit.span.end_line, fixit.span.end_column)?;
+            match start <= end {
+                true => Some((start, end, fixit.replacement.as_str())),
+                false => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    edits.sort();
+
+    // apply back to front so earlier offsets stay valid; skip edits that overlap one already
+    // applied
+    let mut out = text.to_string();
+    let mut limit = text.len();
+    let mut n = 0;
+    for (start, end, replacement) in edits.into_iter().rev() {
+        if end > limit {
+            continue;
+        }
+        out.replace_range(start..end, replacement);
+        limit = start;
+        n += 1;
+    }
+    (out, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item::{FixIt, Span};
+
+    fn fixit(span: (usize, usize, usize, usize), replacement: &str) -> FixIt {
+        FixIt {
+            path: "foo.c".to_string(),
+            span: Span {
+                start_line: span.0,
+                start_column: span.1,
+                end_line: span.2,
+                end_column: span.3,
+            },
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            super::parse(r#"fix-it:"foo.c":{29:33-29:33}:";""#),
+            Some(fixit((29, 33, 29, 33), ";"))
+        );
+        assert_eq!(
+            super::parse(r#"fix-it:"foo.c":{3:5-3:11}:"\"lseek\"\n\\""#),
+            Some(fixit((3, 5, 3, 11), "\"lseek\"\n\\"))
+        );
+        assert_eq!(super::parse("foo.c:1:2: error: bar"), None);
+    }
+
+    #[test]
+    fn test_unescape_octal() {
+        assert_eq!(super::unescape(r"a\011b"), "a\tb");
+    }
+
+    #[test]
+    fn test_from_hint_insertion() {
+        let body = [
+            "        zip->releaseEntry(entry)",
+            "                                ^",
+            "                                ;",
+        ];
+        assert_eq!(
+            super::from_hint("foo.c", 29, &body),
+            Some(fixit((29, 33, 29, 33), ";"))
+        );
+    }
+
+    #[test]
+    fn test_from_hint_replacement() {
+        let body = ["    lseeek(fd, 0, 0);", "    ^~~~~~", "    lseek"];
+        assert_eq!(
+            super::from_hint("foo.c", 3, &body),
+            Some(fixit((3, 5, 3, 11), "lseek"))
+        );
+    }
+
+    #[test]
+    fn test_from_hint_none() {
+        let body = [
+            "            for (int i = 0; i < NUM_BRUSHES; i++) {",
+            "                                ^",
+            "  symbol:   variable NUM_BRUSHES",
+        ];
+        assert_eq!(super::from_hint("foo.java", 228, &body), None);
+        assert_eq!(super::from_hint("foo.c", 1, &["}", "^"]), None);
+    }
+
+    #[test]
+    fn test_from_hint_gcc_label() {
+        let body = [
+            "   12 |   foo(x);",
+            "      |   ^~~~~~",
+            "      |   |",
+            "      |   int",
+        ];
+        assert_eq!(super::from_hint("foo.c", 12, &body), None);
+    }
+
+    #[test]
+    fn test_from_hint_summary() {
+        let body = ["}", "^", "1 warning generated."];
+        assert_eq!(super::from_hint("foo.c", 7, &body), None);
+    }
+
+    #[test]
+    fn test_from_hint_before_summary() {
+        let body = [
+            "    lseeek(fd, 0, 0);",
+            "    ^~~~~~",
+            "    lseek",
+            "1 warning generated.",
+        ];
+        assert_eq!(
+            super::from_hint("foo.c", 3, &body),
+            Some(fixit((3, 5, 3, 11), "lseek"))
+        );
+    }
+
+    #[test]
+    fn test_apply_to() {
+        let text = "int main() {\n    lseeek(fd, 0, 0)\n}\n";
+        let a = fixit((2, 5, 2, 11), "lseek");
+        let b = fixit((2, 21, 2, 21), ";");
+        let (out, n) = super::apply_to(text, &[&a, &b]);
+        assert_eq!(n, 2);
+        assert_eq!(out, "int main() {\n    lseek(fd, 0, 0);\n}\n");
+    }
+
+    #[test]
+    fn test_apply_to_skips_overlapping_and_invalid() {
+        let text = "abcdef\n";
+        let a = fixit((1, 2, 1, 5), "X");
+        let b = fixit((1, 3, 1, 4), "Y");
+        let c = fixit((9, 1, 9, 1), "Z");
+        let (out, n) = super::apply_to(text, &[&a, &b, &c]);
+        assert_eq!(n, 1);
+        assert_eq!(out, "abYdef\n");
+    }
+
+    #[test]
+    fn test_apply_to_end_of_line() {
+        let text = "int x = 1 // one\nint y = 2;\n";
+        let a = fixit((1, 10, 1, Span::END_OF_LINE), ";");
+        let (out, n) = super::apply_to(text, &[&a]);
+        assert_eq!(n, 1);
+        assert_eq!(out, "int x = 1;\nint y = 2;\n");
+    }
+
+    #[test]
+    fn test_apply_missing_file() {
+        let root = std::path::Path::new("/nonexistent-root");
+        let e = super::apply(&[fixit((1, 1, 1, 1), ";")], root, true, &mut Vec::new()).unwrap_err();
+        assert!(e.to_string().starts_with("/nonexistent-root/foo.c: "));
+    }
+
+    #[test]
+    fn test_apply_dry_run() {
+        let dir = std::env::temp_dir().join(format!("soong-digest-fixit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("foo.c"), "int x = 1\n").unwrap();
+
+        let mut out = Vec::new();
+        let n = super::apply(&[fixit((1, 10, 1, 10), ";")], &dir, true, &mut out).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "--- a/foo.c\n+++ b/foo.c\n@@ -1 +1 @@\n-int x = 1\n+int x = 1;\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("foo.c")).unwrap(),
+            "int x = 1\n"
+        );
+
+        let n = super::apply(&[fixit((1, 10, 1, 10), ";")], &dir, false, &mut Vec::new()).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("foo.c")).unwrap(),
+            "int x = 1;\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use crate::digest_error::{DigestError, ParseError};
+use crate::error;
+use crate::input;
+use crate::item::Item;
+use crate::output::{display_items, OutputFormat};
+use crate::warning;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use termcolor::ColorChoice;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    Errors,
+    Warnings,
+    Verbose,
+}
+
+/// A log that is still being written. Each poll reads what was appended to the log since the
+/// previous poll, and parses the part of it that has been completed.
+pub struct Tail {
+    path: PathBuf,
+    kind: Kind,
+    /// Open once soong_ui has created the log.
+    file: Option<File>,
+    /// The number of bytes read from the file.
+    len: u64,
+    stream: input::Stream,
+    /// The text read but not parsed yet, because its last block may still be incomplete.
+    text: String,
+    /// The number of lines parsed before `text`.
+    line: usize,
+    finished: bool,
+}
+
+impl Tail {
+    pub fn new(path: PathBuf, kind: Kind) -> Tail {
+        Tail {
+            path,
+            kind,
+            file: None,
+            len: 0,
+            stream: input::Stream::default(),
+            text: String::new(),
+            line: 0,
+            finished: false,
+        }
+    }
+
+    /// Whether the log showed that the build is over at the last poll.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Parse the blocks completed since the last poll. The last block is only complete once
+    /// the next one starts, unless `flush` is set. error.log has no other sign that a block is
+    /// complete, and soong_ui writes each block at once, so its last block is also parsed once the
+    /// log stopped growing for a poll.
+    ///
+    /// A block that cannot be parsed is skipped, and reported as a `DigestError::Parse`.
+    pub fn poll(&mut self, flush: bool) -> Result<Vec<Item<'static>>, DigestError> {
+        let unchanged = self.read().map_err(|e| DigestError::io(&self.path, e))? == 0;
+        self.finished = self.finished || is_finished(&self.text, unchanged);
+        let idle = unchanged && matches!(self.kind, Kind::Errors);
+        let end = match flush || self.finished || idle {
+            true => self.text.len(),
+            false => boundary(&self.text, self.kind),
+        };
+        let region = &self.text[..end];
+        let items = match parse(region, self.kind) {
+            Ok(items) => Ok(items.into_iter().map(Item::into_owned).collect()),
+            Err(mut e) => {
+                e.line = e.line.map(|line| line + self.line);
+                Err(DigestError::parse(&self.path, e))
+            }
+        };
+        self.line += region.matches('\n').count();
+        self.text.drain(..end);
+        items
+    }
+
+    /// Read what was appended to the log since the last poll into `text`. Return the number of
+    /// bytes read.
+    fn read(&mut self) -> io::Result<u64> {
+        let len = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            // soong_ui has not created the log yet
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if len < self.len {
+            // soong_ui rotated the log away when a new build started
+            *self = Tail::new(self.path.clone(), self.kind);
+        }
+        if self.file.is_none() {
+            self.file = Some(File::open(&self.path)?);
+        }
+        let mut raw = vec![];
+        self.file.as_mut().unwrap().read_to_end(&mut raw)?;
+        self.len += raw.len() as u64;
+        let text = self.stream.push(&raw)?;
+        self.text.push_str(&text);
+        Ok(raw.len() as u64)
+    }
+}
+
+/// The offset of the first block in `haystack` that may still be incomplete: the last FAILED
+/// block in error.log, or the output of the last step ninja started in verbose.log.
+pub fn boundary(haystack: &str, kind: Kind) -> usize {
+    lazy_static! {
+        static ref RE_FAILED: Regex = Regex::new(r"(?m)^FAILED: ").unwrap();
+        static ref RE_STEP: Regex = Regex::new(r"(?m)^\[\d+/\d+\] ").unwrap();
+    }
+    let re: &Regex = match kind {
+        Kind::Errors => &RE_FAILED,
+        Kind::Warnings | Kind::Verbose => &RE_STEP,
+    };
+    re.find_iter(haystack).last().map_or(0, |m| m.start())
+}
+
+/// Ninja reports when a failed build stops; a successful build is over once its last step has
+/// started and the log stopped growing.
+fn is_finished(haystack: &str, unchanged: bool) -> bool {
+    lazy_static! {
+        static ref RE_STOPPED: Regex =
+            Regex::new(r"(?m)^(?:status: )?ninja: (?:build stopped|no work to do)").unwrap();
+        static ref RE_STEP: Regex = Regex::new(r"(?m)^\[(\d+)/(\d+)\] ").unwrap();
+    }
+    if RE_STOPPED.is_match(haystack) {
+        return true;
+    }
+    unchanged
+        && RE_STEP
+            .captures_iter(haystack)
+            .last()
+            .is_some_and(|caps| caps[1] == caps[2])
+}
+
+/// Parse a region of a log, ending at a block boundary. Unlike `error::parse`, a region of
+/// error.log without any FAILED block is not an error.
+pub fn parse(region: &str, kind: Kind) -> Result<Vec<Item<'_>>, ParseError> {
+    let mut items = vec![];
+    match kind {
+        Kind::Errors => match error::parse(region) {
+            Ok(iter) => items.extend(iter),
+            // nothing but progress output so far
+            Err(_) if !region.contains("FAILED: ") => {}
+            Err(e) => return Err(e),
+        },
+        Kind::Warnings => items.extend(warning::parse(region)?),
+        Kind::Verbose => {
+            items.extend(error::parse_verbose(region)?);
+            items.extend(warning::parse(region)?);
+        }
+    }
+    Ok(items)
+}
+
+/// Poll the logs until the build is over, printing each new item once. Return the number of
+/// items printed. error.log does not show when the build is over, so following only error.log
+/// runs until interrupted.
+pub fn run(
+    mut tails: Vec<Tail>,
+    output_format: OutputFormat,
+    color_choice: ColorChoice,
+) -> Result<usize, DigestError> {
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    loop {
+        let finished = tails.iter().any(Tail::finished);
+        let mut items = vec![];
+        for tail in &mut tails {
+            match tail.poll(finished) {
+                Ok(new) => items.extend(new),
+                // the block is skipped; the build goes on, and so does following it
+                Err(e @ DigestError::Parse { .. }) => eprintln!("soong-digest: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        let items = items
+            .into_iter()
+            .filter(|item| seen.insert(item.key()))
+            .collect::<Vec<_>>();
+        total += display_items(items.into_iter(), output_format, color_choice)
+            .map_err(DigestError::Output)?;
+        if finished {
+            return Ok(total);
+        }
+        if !tails.iter().any(Tail::finished) {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind, Tail};
+    use crate::digest_error::DigestError;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn test_boundary() {
+        let haystack = "[1/3] foo\nfoo.c:1:2: warning: bar\n[2/3] baz\nbaz.c:3:4: warn";
+        assert_eq!(super::boundary(haystack, Kind::Warnings), 34);
+        assert_eq!(super::boundary(haystack, Kind::Errors), 0);
+        assert_eq!(super::boundary("", Kind::Warnings), 0);
+    }
+
+    #[test]
+    fn test_is_finished() {
+        assert!(super::is_finished(
+            "[1/2] foo\nstatus: ninja: build stopped: subcommand failed.\n",
+            false
+        ));
+        assert!(!super::is_finished("[1/2] foo\n[2/2] bar\n", false));
+        assert!(super::is_finished("[1/2] foo\n[2/2] bar\n", true));
+        assert!(!super::is_finished("[1/2] foo\n", true));
+    }
+
+    #[test]
+    fn test_poll() {
+        let path = std::env::temp_dir().join(format!("soong-digest-follow-{}", std::process::id()));
+        let mut tail = Tail::new(path.clone(), Kind::Warnings);
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+
+        fs::write(&path, "[1/3] cc foo.c\nfoo.c:1:2: warning: foo\n").unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+
+        fs::write(
+            &path,
+            "[1/3] cc foo.c\nfoo.c:1:2: warning: foo\n  body\n[2/3] cc bar.c\nbar.c:3:4: warning: bar\n",
+        )
+        .unwrap();
+        let items = tail.poll(false).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "foo");
+        assert_eq!(items[0].body, Some("  body".into()));
+        assert!(!tail.finished());
+
+        fs::write(
+            &path,
+            "[1/3] cc foo.c\nfoo.c:1:2: warning: foo\n  body\n[2/3] cc bar.c\nbar.c:3:4: warning: bar\n\
+             status: ninja: build stopped: subcommand failed.\n",
+        )
+        .unwrap();
+        let items = tail.poll(false).unwrap();
+        assert!(tail.finished());
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "bar");
+
+        // a new build starts over with a fresh log
+        fs::write(&path, "[1/1] cc baz.c\nbaz.c:5:6: warning: baz\n").unwrap();
+        let items = tail.poll(true).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "baz");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_poll_error_log() {
+        let path =
+            std::env::temp_dir().join(format!("soong-digest-follow-errors-{}", std::process::id()));
+        let block = |name| {
+            format!(
+                "FAILED: {0}.o\nOutputs: {0}.o\nError: exited with code: 1\nCommand: cc {0}.c\n\
+                 Output:\n{0}.c:1:2: error: {0}\n",
+                name
+            )
+        };
+        let mut tail = Tail::new(path.clone(), Kind::Errors);
+        fs::write(&path, block("foo")).unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+
+        // the log stopped growing, so the last block is complete
+        let items = tail.poll(false).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "foo");
+
+        fs::write(&path, block("foo") + &block("bar")).unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+        let items = tail.poll(false).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "bar");
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+        assert!(!tail.finished());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_poll_parse_error() {
+        let path =
+            std::env::temp_dir().join(format!("soong-digest-follow-bad-{}", std::process::id()));
+        let good = "FAILED: foo.o\nOutputs: foo.o\nError: exited with code: 1\nCommand: cc foo.c\n\
+                    Output:\nfoo.c:1:2: error: foo\n";
+        let bad = "FAILED: bar.o\nsomething went wrong\n";
+        let mut tail = Tail::new(path.clone(), Kind::Errors);
+        fs::write(&path, good).unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+        assert_eq!(tail.poll(false).unwrap().len(), 1);
+
+        fs::write(&path, good.to_string() + bad).unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+        match tail.poll(false) {
+            Err(DigestError::Parse { error, .. }) => assert_eq!(error.line, Some(7)),
+            _ => panic!("expected a parse error"),
+        }
+
+        // the bad block is skipped, and the next one is parsed
+        fs::write(&path, good.to_string() + bad + &good.replace("foo", "baz")).unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+        let items = tail.poll(false).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "baz");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_poll_gzip() {
+        let path = std::env::temp_dir().join(format!(
+            "soong-digest-follow-gzip-{}.gz",
+            std::process::id()
+        ));
+        let log =
+            "[1/2] cc foo.c\nfoo.c:1:2: warning: foo\n[2/2] cc bar.c\nbar.c:3:4: warning: bar\n";
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(log.as_bytes()).unwrap();
+        let raw = encoder.finish().unwrap();
+
+        // the compressed stream is written a piece at a time
+        let mut tail = Tail::new(path.clone(), Kind::Warnings);
+        let (head, rest) = raw.split_at(raw.len() / 2);
+        fs::write(&path, head).unwrap();
+        assert_eq!(tail.poll(false).unwrap().len(), 0);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(rest)
+            .unwrap();
+        let items = tail.poll(false).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "foo");
+
+        // the last step started and the log stopped growing
+        let items = tail.poll(false).unwrap();
+        assert!(tail.finished());
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].subject, "bar");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// The function GCC reported diagnostics to be in.
+///
+/// GCC prefixes diagnostics with context lines such as "foo.c: In function 'bar':", and later
+/// "foo.c: At top level:" once it leaves the function; clang has no equivalent.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context<'a> {
+    path: &'a str,
+    function: Option<&'a str>,
+}
+
+impl<'a> Context<'a> {
+    /// Update the context if `line` is a GCC context line. Return true if it was.
+    pub fn update(&mut self, line: &'a str) -> bool {
+        lazy_static! {
+            static ref RE_FUNCTION: Regex = Regex::new(
+                r"^(\S+): In (?:static |member )*(?:function|constructor|destructor) [‘'](.*)[’']:$"
+            )
+            .unwrap();
+            static ref RE_OTHER: Regex =
+                Regex::new(r"^(\S+): (?:At top level|At global scope|In lambda function|In instantiation of .*):$")
+                    .unwrap();
+        }
+        if !line.ends_with(':') {
+            return false;
+        }
+        if let Some(caps) = RE_FUNCTION.captures(line) {
+            self.path = caps.get(1).unwrap().as_str();
+            self.function = Some(caps.get(2).unwrap().as_str());
+            return true;
+        }
+        if let Some(caps) = RE_OTHER.captures(line) {
+            self.path = caps.get(1).unwrap().as_str();
+            self.function = None;
+            return true;
+        }
+        false
+    }
+
+    pub fn reset(&mut self) {
+        *self = Context::default();
+    }
+
+    /// The function a diagnostic in `path` belongs to.
+    pub fn function(&self, path: &str) -> Option<&'a str> {
+        match path == self.path {
+            true => self.function,
+            false => None,
+        }
+    }
+}
+
+/// Lines GCC prints that are not part of any diagnostic: the include stack leading up to the
+/// next diagnostic, and the summary at the end.
+pub fn is_noise(line: &str) -> bool {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"^(?:In file included from \S+|\s+from \S+[:,]|cc1(?:plus)?: (?:all|some) warnings being treated as errors)$"
+        )
+        .unwrap();
+    }
+    RE.is_match(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+
+    #[test]
+    fn test_context() {
+        let mut context = Context::default();
+        assert_eq!(context.function("foo.c"), None);
+
+        assert!(context.update("foo.c: In function 'main':"));
+        assert_eq!(context.function("foo.c"), Some("main"));
+        assert_eq!(context.function("bar.h"), None);
+
+        assert!(context.update("foo.cpp: In member function ‘void Foo::bar(int)’:"));
+        assert_eq!(context.function("foo.cpp"), Some("void Foo::bar(int)"));
+
+        assert!(context.update("foo.cpp: At global scope:"));
+        assert_eq!(context.function("foo.cpp"), None);
+
+        assert!(!context.update("foo.c:10:5: warning: unused variable 'x' [-Wunused-variable]"));
+
+        context.update("foo.c: In function 'main':");
+        context.reset();
+        assert_eq!(context.function("foo.c"), None);
+    }
+
+    #[test]
+    fn test_is_noise() {
+        assert!(super::is_noise("In file included from foo.h:3,"));
+        assert!(super::is_noise("                 from foo.c:1:"));
+        assert!(super::is_noise(
+            "cc1plus: all warnings being treated as errors"
+        ));
+        assert!(super::is_noise(
+            "cc1: some warnings being treated as errors"
+        ));
+        assert!(!super::is_noise(
+            "foo.c:3:20: note: in expansion of macro 'FOO'"
+        ));
+        assert!(!super::is_noise("   10 |     int x;"));
+    }
+}
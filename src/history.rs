@@ -0,0 +1,142 @@
+use crate::item::Item;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A warning seen in one or more of the recent builds.
+#[derive(Debug)]
+pub struct Entry<'a> {
+    /// The warning as reported by the most recent build that had it.
+    pub item: Item<'a>,
+    /// The number of builds that had the warning.
+    pub seen: usize,
+    /// The oldest build that had the warning, counted in builds before the latest one.
+    pub first_seen: usize,
+}
+
+/// Find the logs soong_ui rotated out for earlier builds next to `path`: for out/verbose.log.gz
+/// these are out/verbose.log.1.gz, out/verbose.log.2.gz and so on. Return `path` followed by the
+/// rotated logs, newest first.
+pub fn find_builds(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let (stem, extension) = match name.rfind('.') {
+        Some(i) => (&name[..i], &name[i..]),
+        None => (name, ""),
+    };
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let mut rotated = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let n = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix(stem))
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.strip_suffix(extension))
+            .and_then(|n| n.parse::<usize>().ok());
+        if let Some(n) = n {
+            rotated.push((n, path.with_file_name(entry.file_name())));
+        }
+    }
+    rotated.sort();
+    Ok(std::iter::once(path.to_path_buf())
+        .chain(rotated.into_iter().map(|(_, path)| path))
+        .collect())
+}
+
+/// Merge the warnings of several builds, newest first.
+pub fn digest(builds: Vec<Vec<Item<'_>>>) -> Vec<Entry<'_>> {
+    let mut entries: Vec<Entry> = vec![];
+    let mut index: HashMap<_, usize> = HashMap::new();
+    for (i, mut items) in builds.into_iter().enumerate() {
+        items.sort();
+        items.dedup();
+        for item in items {
+            let key = item.key();
+            match index.get(&key) {
+                Some(&j) => {
+                    let entry = &mut entries[j];
+                    entry.seen += 1;
+                    entry.first_seen = i;
+                }
+                None => {
+                    index.insert(key, entries.len());
+                    entries.push(Entry {
+                        item,
+                        seen: 1,
+                        first_seen: i,
+                    });
+                }
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.item.cmp(&b.item));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::warning;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_find_builds() {
+        let dir = std::env::temp_dir().join(format!("soong-digest-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in &[
+            "verbose.log.gz",
+            "verbose.log.1.gz",
+            "verbose.log.2.gz",
+            "verbose.log.10.gz",
+            "verbose.log.x.gz",
+            "error.log",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let builds = super::find_builds(&dir.join("verbose.log.gz")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            builds,
+            [
+                "verbose.log.gz",
+                "verbose.log.1.gz",
+                "verbose.log.2.gz",
+                "verbose.log.10.gz"
+            ]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect::<Vec<PathBuf>>()
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        let parse = |s| warning::parse(s).unwrap().collect::<Vec<_>>();
+        let entries = super::digest(vec![
+            parse("a.c:1:1: warning: foo\nb.c:2:2: warning: bar\nlatest\n"),
+            parse("a.c:1:1: warning: foo\nb.c:2:2: warning: bar\n"),
+            parse("b.c:2:2: warning: bar\nc.c:3:3: warning: baz\n"),
+        ]);
+        assert_eq!(entries.len(), 3);
+
+        let e = &entries[0];
+        assert_eq!(e.item.path, "a.c");
+        assert_eq!((e.seen, e.first_seen), (2, 1));
+
+        let e = &entries[1];
+        assert_eq!(e.item.path, "b.c");
+        assert_eq!(e.item.body, Some("latest".into()));
+        assert_eq!((e.seen, e.first_seen), (3, 2));
+
+        let e = &entries[2];
+        assert_eq!(e.item.path, "c.c");
+        assert_eq!((e.seen, e.first_seen), (1, 2));
+    }
+}
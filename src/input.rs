@@ -0,0 +1,486 @@
+use crate::ansi::strip_ansi_escape;
+use flate2::read::MultiGzDecoder;
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use xz2::read::XzDecoder;
+use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+#[derive(Debug, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// Identify the compression by the magic bytes at the start of the file, regardless of the
+    /// file name.
+    fn detect(raw: &[u8]) -> Compression {
+        if raw.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if raw.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if raw.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Whether the decoder failed because the stream ended early, rather than because the data
+    /// is corrupt. All three report running out of input as an unexpected EOF, but flate2 reports
+    /// a stream cut inside the last deflate block as corrupt.
+    fn is_truncated(&self, e: &io::Error) -> bool {
+        match (self, e.kind()) {
+            (_, io::ErrorKind::UnexpectedEof) => true,
+            (Compression::Gzip, io::ErrorKind::InvalidInput) => {
+                e.to_string() == "corrupt deflate stream"
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct Input {
+    /// The text of the log, without color codes.
+    pub contents: String,
+    /// The compressed stream ended early, typically because the build writing it is still
+    /// running. `contents` holds the complete lines decompressed up to that point.
+    pub partial: bool,
+}
+
+/// Read a log file, decompressing it if it is gzip (including concatenated gzip members), zstd or
+/// xz compressed. A path of "-" reads from stdin.
+pub fn read(path: &Path) -> io::Result<Input> {
+    decode(&read_raw(path)?)
+}
+
+/// Read a log file as is, without decompressing it. A path of "-" reads from stdin.
+pub fn read_raw(path: &Path) -> io::Result<Vec<u8>> {
+    if is_stdin(path) {
+        let mut raw = vec![];
+        io::stdin().read_to_end(&mut raw)?;
+        return Ok(raw);
+    }
+    fs::read(path)
+}
+
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+pub fn decode(raw: &[u8]) -> io::Result<Input> {
+    let mut bytes = vec![];
+    let compression = Compression::detect(raw);
+    let result = match compression {
+        Compression::None => {
+            bytes.extend_from_slice(raw);
+            Ok(0)
+        }
+        Compression::Gzip => MultiGzDecoder::new(raw).read_to_end(&mut bytes),
+        Compression::Zstd => zstd::stream::read::Decoder::new(raw)?.read_to_end(&mut bytes),
+        Compression::Xz => XzDecoder::new_multi_decoder(raw).read_to_end(&mut bytes),
+    };
+    // read_to_end keeps what was decompressed before the stream ran out
+    let partial = match result {
+        Ok(_) => false,
+        Err(ref e) if compression.is_truncated(e) => true,
+        Err(e) => return Err(e),
+    };
+    if partial {
+        // the last line is most likely cut short
+        let end = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        bytes.truncate(end);
+    }
+    Ok(Input {
+        contents: text(bytes),
+        partial,
+    })
+}
+
+/// The text of decompressed log lines, without color codes.
+fn text(bytes: Vec<u8>) -> String {
+    // compilers echo source lines verbatim, and not every source file is UTF-8
+    let contents = match String::from_utf8(bytes) {
+        Ok(contents) => contents,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    };
+    // strip the color codes once, here, so that the items parsed from a colored log can borrow
+    // from the stripped text instead of each parser stripping and copying it again
+    let stripped = match strip_ansi_escape(&contents) {
+        Cow::Owned(stripped) => Some(stripped),
+        Cow::Borrowed(_) => None,
+    };
+    stripped.unwrap_or(contents)
+}
+
+/// Decodes a log that is still being written, a piece at a time. Unlike `decode`, a compressed
+/// stream that ends early is not partial: decoding resumes once the rest is written.
+#[derive(Default)]
+pub struct Stream {
+    /// Unknown until the magic bytes have been written.
+    decoder: Option<Decoder>,
+    /// Compressed bytes the decoder could not use yet, such as half a gzip header.
+    raw: Vec<u8>,
+    /// Decompressed bytes after the last complete line.
+    bytes: Vec<u8>,
+}
+
+enum Decoder {
+    None,
+    Gzip(Gzip),
+    Zstd(zstd::stream::raw::Decoder<'static>),
+    Xz(xz2::stream::Stream),
+}
+
+/// Where a gzip decoder is in the current member. flate2's own gzip decoders cannot resume after
+/// running out of input in a header or trailer, so only the deflate data is left to flate2.
+enum Gzip {
+    Header,
+    Data(Decompress, Crc),
+    Trailer(Crc),
+}
+
+impl Stream {
+    /// Decode `raw`, the bytes appended to the log since the last call. Return the text of the
+    /// lines it completed, without color codes.
+    pub fn push(&mut self, raw: &[u8]) -> io::Result<String> {
+        self.raw.extend_from_slice(raw);
+        if self.decoder.is_none() {
+            // enough for any of the magic bytes
+            if self.raw.len() < 6 {
+                return Ok(String::new());
+            }
+            self.decoder = Some(match Compression::detect(&self.raw) {
+                Compression::None => Decoder::None,
+                Compression::Gzip => Decoder::Gzip(Gzip::Header),
+                Compression::Zstd => Decoder::Zstd(zstd::stream::raw::Decoder::new()?),
+                Compression::Xz => Decoder::Xz(
+                    xz2::stream::Stream::new_stream_decoder(u64::MAX, xz2::stream::CONCATENATED)
+                        .map_err(io::Error::from)?,
+                ),
+            });
+        }
+        let used = match self.decoder.as_mut().unwrap() {
+            Decoder::None => {
+                self.bytes.extend_from_slice(&self.raw);
+                self.raw.len()
+            }
+            Decoder::Gzip(gzip) => gzip.decode(&self.raw, &mut self.bytes)?,
+            Decoder::Zstd(zstd) => decode_zstd(zstd, &self.raw, &mut self.bytes)?,
+            Decoder::Xz(xz) => decode_xz(xz, &self.raw, &mut self.bytes)?,
+        };
+        self.raw.drain(..used);
+        let end = self
+            .bytes
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let rest = self.bytes.split_off(end);
+        Ok(text(std::mem::replace(&mut self.bytes, rest)))
+    }
+}
+
+const CHUNK: usize = 32 * 1024;
+
+impl Gzip {
+    /// Decode as much of `raw` as possible into `out`. Return the number of bytes used.
+    fn decode(&mut self, raw: &[u8], out: &mut Vec<u8>) -> io::Result<usize> {
+        let corrupt = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut buf = vec![0; CHUNK];
+        let mut pos = 0;
+        loop {
+            match self {
+                Gzip::Header => match gzip_header_len(&raw[pos..])? {
+                    Some(n) => {
+                        pos += n;
+                        *self = Gzip::Data(Decompress::new(false), Crc::new());
+                    }
+                    None => return Ok(pos),
+                },
+                Gzip::Data(inflate, crc) => {
+                    let (total_in, total_out) = (inflate.total_in(), inflate.total_out());
+                    let status = inflate
+                        .decompress(&raw[pos..], &mut buf, FlushDecompress::None)
+                        .map_err(|_| corrupt("corrupt deflate stream"))?;
+                    let n = (inflate.total_out() - total_out) as usize;
+                    pos += (inflate.total_in() - total_in) as usize;
+                    crc.update(&buf[..n]);
+                    out.extend_from_slice(&buf[..n]);
+                    if status == Status::StreamEnd {
+                        *self = Gzip::Trailer(std::mem::replace(crc, Crc::new()));
+                    } else if n == 0 && inflate.total_in() == total_in {
+                        return Ok(pos);
+                    }
+                }
+                Gzip::Trailer(crc) => {
+                    let trailer = match raw.get(pos..pos + 8) {
+                        Some(trailer) => trailer,
+                        None => return Ok(pos),
+                    };
+                    let word = |i: usize| {
+                        u32::from_le_bytes([
+                            trailer[i],
+                            trailer[i + 1],
+                            trailer[i + 2],
+                            trailer[i + 3],
+                        ])
+                    };
+                    if word(0) != crc.sum() || word(4) != crc.amount() {
+                        return Err(corrupt(
+                            "corrupt gzip stream does not have a matching checksum",
+                        ));
+                    }
+                    pos += 8;
+                    // another member may follow
+                    *self = Gzip::Header;
+                }
+            }
+        }
+    }
+}
+
+/// The length of the gzip member header at the start of `raw`, or None if it has not all been
+/// written yet.
+fn gzip_header_len(raw: &[u8]) -> io::Result<Option<usize>> {
+    const FHCRC: u8 = 1 << 1;
+    const FEXTRA: u8 = 1 << 2;
+    const FNAME: u8 = 1 << 3;
+    const FCOMMENT: u8 = 1 << 4;
+    if raw.len() < 10 {
+        return Ok(None);
+    }
+    if raw[..3] != [0x1f, 0x8b, 8] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid gzip header",
+        ));
+    }
+    let flags = raw[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        match raw.get(len..len + 2) {
+            Some(xlen) => len += 2 + usize::from(u16::from_le_bytes([xlen[0], xlen[1]])),
+            None => return Ok(None),
+        }
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            // a zero-terminated string
+            match raw
+                .get(len..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+            {
+                Some(i) => len += i + 1,
+                None => return Ok(None),
+            }
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    match len <= raw.len() {
+        true => Ok(Some(len)),
+        false => Ok(None),
+    }
+}
+
+fn decode_zstd(
+    zstd: &mut zstd::stream::raw::Decoder,
+    raw: &[u8],
+    out: &mut Vec<u8>,
+) -> io::Result<usize> {
+    let mut buf = vec![0; CHUNK];
+    let mut input = InBuffer::around(raw);
+    loop {
+        let pos = input.pos();
+        let mut output = OutBuffer::around(&mut buf[..]);
+        let hint = zstd.run(&mut input, &mut output)?;
+        let n = output.pos();
+        out.extend_from_slice(&buf[..n]);
+        if hint == 0 {
+            // the frame is over; another may follow
+            zstd.reinit()?;
+        }
+        if n < CHUNK && (input.pos() == raw.len() || input.pos() == pos) {
+            return Ok(input.pos());
+        }
+    }
+}
+
+fn decode_xz(xz: &mut xz2::stream::Stream, raw: &[u8], out: &mut Vec<u8>) -> io::Result<usize> {
+    let mut buf = vec![0; CHUNK];
+    let mut pos = 0;
+    loop {
+        let (total_in, total_out) = (xz.total_in(), xz.total_out());
+        xz.process(&raw[pos..], &mut buf, xz2::stream::Action::Run)
+            .map_err(io::Error::from)?;
+        let n = (xz.total_out() - total_out) as usize;
+        pos += (xz.total_in() - total_in) as usize;
+        out.extend_from_slice(&buf[..n]);
+        if n < CHUNK && (pos == raw.len() || xz.total_in() == total_in) {
+            return Ok(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    const TEXT: &str = "[1/2] foo\nfoo.c:10:20: warning: bar\n";
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(b""), Compression::None);
+        assert_eq!(Compression::detect(TEXT.as_bytes()), Compression::None);
+        assert_eq!(Compression::detect(&gzip(TEXT)), Compression::Gzip);
+    }
+
+    #[test]
+    fn test_decode_plain() {
+        let input = super::decode(TEXT.as_bytes()).unwrap();
+        assert_eq!(input.contents, TEXT);
+        assert!(!input.partial);
+    }
+
+    #[test]
+    fn test_decode_color() {
+        let input =
+            super::decode(b"\x1b[1mfoo.c:10:20: \x1b[0;1;35mwarning: \x1b[0mbar\n").unwrap();
+        assert_eq!(input.contents, "foo.c:10:20: warning: bar\n");
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        let input = super::decode(b"foo.c:1:2: warning: caf\xe9\n").unwrap();
+        assert_eq!(input.contents, "foo.c:1:2: warning: caf\u{fffd}\n");
+    }
+
+    #[test]
+    fn test_decode_multi_member_gzip() {
+        let mut raw = gzip("[1/2] foo\n");
+        raw.extend(gzip("foo.c:10:20: warning: bar\n"));
+        assert_eq!(super::decode(&raw).unwrap().contents, TEXT);
+    }
+
+    #[test]
+    fn test_decode_truncated_gzip() {
+        let text = TEXT.repeat(100);
+        let raw = gzip(&text);
+        let input = super::decode(&raw[..raw.len() - 20]).unwrap();
+        assert!(input.partial);
+        assert!(input.contents.ends_with('\n'));
+        assert!(text.starts_with(&input.contents));
+
+        let raw = include_bytes!("../tests/data/idmap-warnings/verbose.log.gz");
+        let input = super::decode(&raw[..raw.len() / 2]).unwrap();
+        assert!(input.partial);
+        assert!(input.contents.len() > 1000);
+    }
+
+    #[test]
+    fn test_decode_truncated_zstd_and_xz() {
+        let text = TEXT.repeat(100);
+        let raw = zstd::stream::encode_all(text.as_bytes(), 0).unwrap();
+        let input = super::decode(&raw[..raw.len() - 10]).unwrap();
+        assert!(input.partial);
+        assert!(text.starts_with(&input.contents));
+
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(text.as_bytes()).unwrap();
+        let raw = encoder.finish().unwrap();
+        let input = super::decode(&raw[..raw.len() - 10]).unwrap();
+        assert!(input.partial);
+        assert!(text.starts_with(&input.contents));
+    }
+
+    #[test]
+    fn test_decode_corrupt() {
+        // a bad checksum in the gzip trailer
+        let mut raw = gzip(&TEXT.repeat(100));
+        let n = raw.len();
+        raw[n - 8] ^= 0xff;
+        assert!(super::decode(&raw).is_err());
+
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(TEXT.repeat(100).as_bytes()).unwrap();
+        let mut raw = encoder.finish().unwrap();
+        raw[40] ^= 0x55;
+        assert!(super::decode(&raw).is_err());
+    }
+
+    /// Feed `raw` to a stream in pieces of `n` bytes, and check that each piece only yields
+    /// complete lines.
+    fn stream(raw: &[u8], n: usize) -> String {
+        let mut stream = super::Stream::default();
+        let mut text = String::new();
+        for piece in raw.chunks(n) {
+            let lines = stream.push(piece).unwrap();
+            assert!(lines.is_empty() || lines.ends_with('\n'));
+            text.push_str(&lines);
+        }
+        text
+    }
+
+    #[test]
+    fn test_stream() {
+        let text = TEXT.repeat(1000);
+        let mut gz = gzip(&text);
+        gz.extend(gzip(&text));
+        let zst = zstd::stream::encode_all(text.as_bytes(), 0).unwrap();
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(text.as_bytes()).unwrap();
+        let xz = encoder.finish().unwrap();
+        for n in [1, 7, 4096] {
+            assert_eq!(stream(text.as_bytes(), n), text);
+            assert_eq!(stream(&gz, n), text.repeat(2));
+            assert_eq!(stream(&zst, n), text);
+            assert_eq!(stream(&xz, n), text);
+        }
+    }
+
+    #[test]
+    fn test_stream_color() {
+        assert_eq!(
+            stream(b"\x1b[1mfoo.c:10:20: \x1b[0;1;35mwarning: \x1b[0mbar\n", 3),
+            "foo.c:10:20: warning: bar\n"
+        );
+    }
+
+    #[test]
+    fn test_stream_corrupt() {
+        let mut raw = gzip(TEXT);
+        let n = raw.len();
+        raw[n - 8] ^= 0xff;
+        assert!(super::Stream::default().push(&raw).is_err());
+    }
+
+    #[test]
+    fn test_decode_zstd() {
+        let raw = zstd::stream::encode_all(TEXT.as_bytes(), 0).unwrap();
+        assert_eq!(Compression::detect(&raw), Compression::Zstd);
+        assert_eq!(super::decode(&raw).unwrap().contents, TEXT);
+    }
+
+    #[test]
+    fn test_decode_xz() {
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        let raw = encoder.finish().unwrap();
+        assert_eq!(Compression::detect(&raw), Compression::Xz);
+        assert_eq!(super::decode(&raw).unwrap().contents, TEXT);
+    }
+}
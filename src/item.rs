@@ -1,20 +1,122 @@
-#[derive(Debug, PartialOrd, PartialEq, Ord, Eq)]
+use crate::crash::Crash;
+use crate::failure::Failure;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(
+    Clone, Copy, Debug, Default, Hash, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize,
+)]
 pub enum ItemType {
+    #[default]
     Error,
     Warning,
+    Crash,
+    Note,
+}
+
+/// A range in a source file. Lines and columns start at 1; `end_column` is the column just past
+/// the end of the range.
+#[derive(Clone, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
 }
 
-#[derive(Debug, PartialOrd, Ord)]
-pub struct Item {
+impl Span {
+    /// An `end_column` for a range that runs to the end of `end_line`, wherever that is.
+    pub const END_OF_LINE: usize = usize::MAX;
+}
+
+/// A suggested edit: replace `span` in `path` with `replacement`. Insertions have an empty span.
+#[derive(Clone, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+pub struct FixIt {
     pub path: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// The log an item was read from, and the product that was being built, if known.
+#[derive(Clone, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub log: String,
+    pub product: Option<String>,
+}
+
+/// A diagnostic found in a log. The text of the item borrows from the log where possible; use
+/// `into_owned` to keep the item around after the log is gone. Fields an item does not have can
+/// be left to `Default`.
+#[derive(Clone, Debug, Default, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Item<'a> {
+    pub path: Cow<'a, str>,
     pub line: Option<usize>,
     pub column: Option<usize>,
-    pub subject: String,
-    pub body: Option<String>,
+    pub subject: Cow<'a, str>,
+    pub body: Option<Cow<'a, str>>,
     pub type_: ItemType,
+    pub failure: Option<Failure>,
+    pub crash: Option<Crash>,
+    pub function: Option<Cow<'a, str>>,
+    pub spans: Vec<Span>,
+    pub fixits: Vec<FixIt>,
+    /// Only recorded when several logs are digested together.
+    pub sources: Vec<Source>,
 }
 
-impl PartialEq for Item {
+/// The fields that identify an item: path, line, column, subject and type.
+pub type Key = (String, Option<usize>, Option<usize>, String, ItemType);
+
+impl Item<'_> {
+    /// The fields that identify an item, see `PartialEq`.
+    pub fn key(&self) -> Key {
+        (
+            self.path.to_string(),
+            self.line,
+            self.column,
+            self.subject.to_string(),
+            self.type_,
+        )
+    }
+
+    /// Copy the text the item borrows from the log.
+    pub fn into_owned(self) -> Item<'static> {
+        Item {
+            path: Cow::Owned(self.path.into_owned()),
+            line: self.line,
+            column: self.column,
+            subject: Cow::Owned(self.subject.into_owned()),
+            body: self.body.map(|body| Cow::Owned(body.into_owned())),
+            type_: self.type_,
+            failure: self.failure,
+            crash: self.crash,
+            function: self
+                .function
+                .map(|function| Cow::Owned(function.into_owned())),
+            spans: self.spans,
+            fixits: self.fixits,
+            sources: self.sources,
+        }
+    }
+}
+
+/// Join the body lines of a diagnostic, all slices of `haystack`. Lines that follow each other in
+/// `haystack` are borrowed as a single slice instead of being copied.
+pub fn join_lines<'a>(haystack: &'a str, lines: &[&'a str]) -> Option<Cow<'a, str>> {
+    let offset = |line: &str| line.as_ptr() as usize - haystack.as_ptr() as usize;
+    let (first, last) = (lines.first()?, lines.last()?);
+    let adjacent = lines
+        .windows(2)
+        .all(|pair| offset(pair[1]) == offset(pair[0]) + pair[0].len() + 1);
+    match adjacent {
+        true => Some(Cow::Borrowed(
+            &haystack[offset(first)..offset(last) + last.len()],
+        )),
+        false => Some(Cow::Owned(lines.join("\n"))),
+    }
+}
+
+impl PartialEq for Item<'_> {
     fn eq(&self, other: &Item) -> bool {
         self.path == other.path
             && self.line == other.line
@@ -25,4 +127,31 @@ impl PartialEq for Item {
     }
 }
 
-impl Eq for Item {}
+impl Eq for Item<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_join_lines() {
+        let haystack = "foo.c:1:2: warning: foo\n  body 1\n  body 2\nfix-it:\n  body 3\n";
+        let lines = haystack.lines().collect::<Vec<_>>();
+        assert!(matches!(
+            super::join_lines(haystack, &lines[1..3]),
+            Some(Cow::Borrowed("  body 1\n  body 2"))
+        ));
+        assert!(matches!(
+            super::join_lines(haystack, &[lines[1], lines[2], lines[4]]),
+            Some(Cow::Owned(ref body)) if body == "  body 1\n  body 2\n  body 3"
+        ));
+        assert_eq!(super::join_lines(haystack, &[]), None);
+
+        let haystack = "foo\r\nbar";
+        let lines = haystack.lines().collect::<Vec<_>>();
+        assert!(matches!(
+            super::join_lines(haystack, &lines),
+            Some(Cow::Owned(ref body)) if body == "foo\nbar"
+        ));
+    }
+}
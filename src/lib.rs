@@ -0,0 +1,22 @@
+//! The parsers and output of soong-digest, as used by the soong-digest binary and the benchmarks.
+
+pub mod ansi;
+pub mod cache;
+mod caret;
+pub mod checkpoint;
+pub mod crash;
+pub mod digest_error;
+pub mod discover;
+pub mod error;
+mod failure;
+pub mod fixit;
+pub mod follow;
+mod gcc;
+mod hash;
+pub mod history;
+pub mod input;
+pub mod item;
+mod ninja;
+pub mod output;
+pub mod sarif;
+pub mod warning;
@@ -1,17 +1,15 @@
-use flate2::read::GzDecoder;
-use std::io::prelude::*;
-use std::path::PathBuf;
-use std::process::exit;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{self, exit, ExitStatus};
 use structopt::StructOpt;
 use termcolor::ColorChoice;
 
-mod ansi;
-mod error;
-mod item;
-mod output;
-mod warning;
-
-use crate::output::{display_items, OutputFormat};
+use soong_digest::cache::{self, Cache};
+use soong_digest::digest_error::DigestError;
+use soong_digest::follow::{self, Kind};
+use soong_digest::item::{Item, Key, Source};
+use soong_digest::output::{display_history, display_items, OutputFormat};
+use soong_digest::{checkpoint, crash, discover, error, fixit, history, input, sarif, warning};
 
 fn try_parse_color_choice(s: &str) -> Result<ColorChoice, &str> {
     match s {
@@ -32,18 +30,43 @@ fn try_parse_output_format(s: &str) -> Result<OutputFormat, &str> {
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "soong-digest")]
+/// Summarize the errors and warnings of an Android build
+///
+/// Without any input, error.log and verbose.log.gz are read from $OUT_DIR, or
+/// $ANDROID_BUILD_TOP/out.
+///
+/// The exit status is the number of items found, up to 254. If an input cannot be read or
+/// parsed, the file and line are reported and the exit status is 255.
 struct Opt {
-    #[structopt(long = "errors", parse(from_os_str))]
+    #[structopt(long = "errors", number_of_values = 1, parse(from_os_str))]
     /// Path to errors file
     ///
-    /// Typically $ANDROID_ROOT/out/build.log.
-    errors: Option<PathBuf>,
+    /// Typically $ANDROID_ROOT/out/build.log. May be plain text, or gzip, zstd or xz compressed.
+    /// Use - to read from stdin. Can be given more than once, and can be a glob pattern, to
+    /// digest the logs of several builds together.
+    errors: Vec<PathBuf>,
 
-    #[structopt(long = "warnings", parse(from_os_str))]
+    #[structopt(long = "warnings", number_of_values = 1, parse(from_os_str))]
     /// Path to warnings file
     ///
-    /// Typically $ANDROID_ROOT/out/verbose.log.gz.
-    warnings: Option<PathBuf>,
+    /// Typically $ANDROID_ROOT/out/verbose.log.gz. May be plain text, or gzip, zstd or xz
+    /// compressed. Use - to read from stdin. Can be given more than once, and can be a glob
+    /// pattern, to digest the logs of several builds together.
+    warnings: Vec<PathBuf>,
+
+    #[structopt(long = "verbose-log", parse(from_os_str))]
+    /// Path to verbose log, to read both errors and warnings from
+    ///
+    /// Typically $ANDROID_ROOT/out/verbose.log.gz. Errors also given with --errors are only
+    /// reported once.
+    verbose_log: Option<PathBuf>,
+
+    #[structopt(long = "sarif", parse(from_os_str))]
+    /// Path to SARIF diagnostics
+    ///
+    /// Either a single file, or a directory to search for .sarif files, typically
+    /// $ANDROID_ROOT/out/soong/.intermediates.
+    sarif: Option<PathBuf>,
 
     #[structopt(
         long = "color",
@@ -64,41 +87,443 @@ struct Opt {
     ///
     /// Valid values are: full, cfile
     output_format: OutputFormat,
+
+    #[structopt(long = "history")]
+    /// Show the warnings of the recent builds instead of the latest one
+    ///
+    /// Reads the logs soong_ui rotated out for earlier builds next to the --verbose-log or
+    /// --warnings file, such as verbose.log.1.gz through verbose.log.5.gz, and reports how many of
+    /// the builds had each warning and when it first appeared.
+    history: bool,
+
+    #[structopt(long = "incremental")]
+    /// Only parse what was added to the logs since the last run
+    ///
+    /// Stores how far each log was parsed, and the items found, in a .soong-digest directory next
+    /// to the log.
+    incremental: bool,
+
+    #[structopt(long = "no-cache")]
+    /// Always parse the logs, instead of reusing the items parsed by an earlier run
+    ///
+    /// Parsed logs are cached in $OUT_DIR/.soong-digest/cache, keyed by their contents.
+    no_cache: bool,
+
+    #[structopt(long = "follow")]
+    /// Digest the logs while the build is running
+    ///
+    /// Prints each error and warning as soon as the compiler is done reporting it, and exits
+    /// once the build is over. error.log does not record the end of the build, so when following
+    /// only error.log, stop with Ctrl-C.
+    follow: bool,
+
+    #[structopt(long = "bundle-crash", parse(from_os_str))]
+    /// Copy compiler crash reproducers to this directory
+    ///
+    /// The preprocessed sources and run scripts clang writes when it crashes are copied here,
+    /// together with a commands.txt listing the crashing commands.
+    bundle_crash: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(name = "fix")]
+    /// Apply the fix-its suggested by the compiler
+    ///
+    /// The fix-its are read from the inputs given before the subcommand, e.g.
+    /// soong-digest --errors out/error.log fix --dry-run
+    Fix {
+        #[structopt(long = "dry-run")]
+        /// Show the changes as a unified diff instead of applying them
+        dry_run: bool,
+
+        #[structopt(long = "root", default_value = ".", parse(from_os_str))]
+        /// Root of the source tree
+        ///
+        /// Typically $ANDROID_ROOT.
+        root: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        /// Only apply fix-its to these files
+        ///
+        /// Paths are relative to the root of the source tree. Defaults to all files.
+        paths: Vec<PathBuf>,
+    },
+
+    #[structopt(name = "run")]
+    /// Run a build, then digest its logs
+    ///
+    /// The build's output is passed through as is, e.g. soong-digest run -- m droid. The exit
+    /// status is the number of items found, and at least 1 if the build failed.
+    Run {
+        #[structopt(raw(required = "true"))]
+        /// The build command and its arguments
+        command: Vec<String>,
+    },
+}
+
+/// Expand the glob patterns among `paths`. Paths without any pattern characters, such as "-", are
+/// returned as is.
+fn expand_globs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, DigestError> {
+    let mut out = vec![];
+    for path in paths {
+        let pattern = path.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            out.push(path.clone());
+            continue;
+        }
+        let matches = glob::glob(&pattern)
+            .map_err(|error| DigestError::Pattern {
+                pattern: pattern.to_string(),
+                error,
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| DigestError::io(e.path().to_path_buf(), e.into()))?;
+        if matches.is_empty() {
+            return Err(DigestError::NoMatch {
+                pattern: pattern.to_string(),
+            });
+        }
+        out.extend(matches);
+    }
+    Ok(out)
+}
+
+/// Record which log the items came from, when several logs are digested together.
+fn add_source<'a>(mut items: Vec<Item<'a>>, path: &Path, product: Option<String>) -> Vec<Item<'a>> {
+    let source = Source {
+        log: path.display().to_string(),
+        product: discover::product(&path.to_string_lossy()).or(product),
+    };
+    for item in &mut items {
+        item.sources = vec![source.clone()];
+    }
+    items
 }
 
-fn try_main() -> Result<usize, String> {
-    let opt = Opt::from_args();
+/// Drop the items an earlier list already reports, and remember the rest. Duplicates within
+/// `items` are kept, so that display can merge their sources.
+fn drop_seen<'a>(mut items: Vec<Item<'a>>, seen: &mut HashSet<Key>) -> Vec<Item<'a>> {
+    items.retain(|item| !seen.contains(&item.key()));
+    seen.extend(items.iter().map(Item::key));
+    items
+}
+
+/// A log read into memory. The items parsed from it borrow from its contents, so it is kept
+/// around until the items have been displayed.
+enum Log {
+    /// The log was parsed by an earlier run.
+    Cached(cache::Entry<'static>),
+    Read {
+        key: String,
+        input: input::Input,
+    },
+}
+
+fn read_log(path: &Path, kind: Kind, cache: Option<&Cache>) -> Result<Log, DigestError> {
+    let raw = input::read_raw(path).map_err(|e| DigestError::io(path, e))?;
+    let key = cache::key(&raw, kind);
+    Ok(match cache.and_then(|cache| cache.load(&key)) {
+        Some(entry) => Log::Cached(entry),
+        None => Log::Read {
+            key,
+            input: input::decode(&raw).map_err(|e| DigestError::io(path, e))?,
+        },
+    })
+}
+
+fn parse_log<'a>(
+    log: &'a mut Log,
+    path: &Path,
+    kind: Kind,
+    with_source: bool,
+    incremental: bool,
+    cache: Option<&Cache>,
+) -> Result<Vec<Item<'a>>, DigestError> {
+    let entry = match log {
+        Log::Cached(entry) => std::mem::take(entry),
+        Log::Read { key, input } => {
+            let contents = &input.contents;
+            let items = match kind {
+                _ if incremental && !input::is_stdin(path) => {
+                    checkpoint::parse(path, contents, kind)
+                }
+                Kind::Errors => error::parse(contents).map(Iterator::collect),
+                Kind::Warnings => warning::parse(contents).map(Iterator::collect),
+                Kind::Verbose => follow::parse(contents, kind),
+            }
+            .map_err(|e| DigestError::parse(path, e))?;
+            let entry = cache::Entry {
+                items,
+                product: discover::product(contents),
+                partial: input.partial,
+            };
+            if let Some(cache) = cache {
+                if let Err(e) = cache.store(key, &entry) {
+                    eprintln!("failed to cache {}: {}", path.display(), e);
+                }
+            }
+            entry
+        }
+    };
+    if entry.partial {
+        eprintln!(
+            "{}: file is truncated, results are partial (is the build still running?)",
+            path.display()
+        );
+    }
+    Ok(match with_source {
+        true => add_source(entry.items, path, entry.product),
+        false => entry.items,
+    })
+}
+
+fn parse_sarif(path: &Path) -> Result<Vec<Item<'static>>, DigestError> {
+    let mut items = vec![];
+    for file in sarif::find_files(path).map_err(|e| DigestError::io(path, e))? {
+        let contents = std::fs::read_to_string(&file).map_err(|e| DigestError::io(&file, e))?;
+        items.extend(sarif::parse(&contents).map_err(|e| DigestError::parse(&file, e))?);
+    }
+    Ok(items)
+}
+
+fn run_build(command: &[String]) -> Result<ExitStatus, DigestError> {
+    process::Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .map_err(|error| DigestError::Run {
+            command: command[0].clone(),
+            error,
+        })
+}
+
+fn try_main() -> Result<usize, DigestError> {
+    let mut opt = Opt::from_args();
+    // run the build first, so the logs discovered below are the ones it wrote
+    let build_status = match &opt.command {
+        Some(Command::Run { command }) => Some(run_build(command)?),
+        _ => None,
+    };
+    opt.errors = expand_globs(&opt.errors)?;
+    opt.warnings = expand_globs(&opt.warnings)?;
+    if opt.errors.is_empty()
+        && opt.warnings.is_empty()
+        && opt.verbose_log.is_none()
+        && opt.sarif.is_none()
+    {
+        let logs = discover::find()?;
+        for path in logs.errors.iter().chain(&logs.verbose_log) {
+            eprintln!("{}", discover::describe(path));
+        }
+        opt.errors = logs.errors.into_iter().collect();
+        opt.verbose_log = logs.verbose_log;
+    }
+    let stdin_inputs = opt
+        .errors
+        .iter()
+        .chain(&opt.warnings)
+        .chain(&opt.verbose_log)
+        .filter(|path| input::is_stdin(path))
+        .count();
+    if stdin_inputs > 1 {
+        return Err(DigestError::Usage("only one input can be read from stdin"));
+    }
+    // a checkpoint is only useful if the log changed, the cache only if it did not
+    let cache = match opt.no_cache || opt.incremental {
+        false => discover::env_out_dir().map(|out_dir| Cache::new(&out_dir)),
+        true => None,
+    };
+    let cache = cache.as_ref();
+    if opt.history {
+        let path = opt
+            .verbose_log
+            .as_ref()
+            .or(opt.warnings.first())
+            .filter(|path| !input::is_stdin(path))
+            .ok_or(DigestError::Usage(
+                "--history requires a --verbose-log or --warnings file",
+            ))?;
+        let builds = history::find_builds(path).map_err(|e| DigestError::io(path, e))?;
+        let entries = history::digest(
+            builds
+                .iter()
+                .map(|path| {
+                    let mut log = read_log(path, Kind::Warnings, cache)?;
+                    // copy the items, rather than keep the logs of all builds in memory
+                    Ok(
+                        parse_log(&mut log, path, Kind::Warnings, false, false, cache)?
+                            .into_iter()
+                            .map(Item::into_owned)
+                            .collect(),
+                    )
+                })
+                .collect::<Result<_, DigestError>>()?,
+        );
+        let n = display_history(&entries, &builds, opt.output_format, opt.color_choice)
+            .map_err(DigestError::Output)?;
+        return Ok(n);
+    }
+
+    if opt.follow {
+        if stdin_inputs > 0 {
+            return Err(DigestError::Usage("--follow cannot read from stdin"));
+        }
+        let tails = opt
+            .errors
+            .iter()
+            .map(|path| follow::Tail::new(path.clone(), Kind::Errors))
+            .chain(
+                opt.warnings
+                    .iter()
+                    .map(|path| follow::Tail::new(path.clone(), Kind::Warnings)),
+            )
+            .chain(
+                opt.verbose_log
+                    .iter()
+                    .map(|path| follow::Tail::new(path.clone(), Kind::Verbose)),
+            )
+            .collect();
+        return follow::run(tails, opt.output_format, opt.color_choice);
+    }
+
+    let mut error_logs = opt
+        .errors
+        .iter()
+        .map(|path| read_log(path, Kind::Errors, cache))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut verbose_log = opt
+        .verbose_log
+        .as_ref()
+        .map(|path| read_log(path, Kind::Verbose, cache))
+        .transpose()?;
+    let mut warning_logs = opt
+        .warnings
+        .iter()
+        .map(|path| read_log(path, Kind::Warnings, cache))
+        .collect::<Result<Vec<_>, _>>()?;
+    let with_source = opt.errors.len() > 1 || opt.warnings.len() > 1;
+    let errors = match opt.errors.len() {
+        0 => None,
+        _ => Some(
+            opt.errors
+                .iter()
+                .zip(&mut error_logs)
+                .map(|(path, log)| {
+                    parse_log(log, path, Kind::Errors, with_source, opt.incremental, cache)
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let verbose = opt
+        .verbose_log
+        .as_ref()
+        .zip(verbose_log.as_mut())
+        .map(|(path, log)| parse_log(log, path, Kind::Verbose, false, opt.incremental, cache))
+        .transpose()?;
+    // display everything from verbose.log together with error.log, so that errors found in
+    // both are deduplicated
+    let errors = match verbose {
+        Some(mut items) => {
+            items.extend(errors.into_iter().flatten());
+            Some(items)
+        }
+        None => errors,
+    };
+    let warnings = match opt.warnings.len() {
+        0 => None,
+        _ => Some(
+            opt.warnings
+                .iter()
+                .zip(&mut warning_logs)
+                .map(|(path, log)| {
+                    parse_log(
+                        log,
+                        path,
+                        Kind::Warnings,
+                        with_source,
+                        opt.incremental,
+                        cache,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let sarif = opt
+        .sarif
+        .as_ref()
+        .map(|path| parse_sarif(path))
+        .transpose()?;
+    // a warning in a failing step is in error.log as well as in verbose.log; report it once
+    let mut seen = HashSet::new();
+    let errors = errors.map(|items| drop_seen(items, &mut seen));
+    let warnings = warnings.map(|items| drop_seen(items, &mut seen));
+    let sarif = sarif.map(|items| drop_seen(items, &mut seen));
+
+    if let Some(Command::Fix {
+        dry_run,
+        root,
+        paths,
+    }) = &opt.command
+    {
+        let fixits = errors
+            .iter()
+            .chain(&warnings)
+            .chain(&sarif)
+            .flatten()
+            .flat_map(|item| &item.fixits)
+            .filter(|fixit| paths.is_empty() || paths.iter().any(|p| p == Path::new(&fixit.path)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let n = fixit::apply(&fixits, root, *dry_run, &mut std::io::stdout())?;
+        return Ok(n);
+    }
+
     let mut total = 0;
 
-    if opt.errors.is_some() {
-        let contents = std::fs::read_to_string(opt.errors.unwrap()).expect("failed to read file");
-        let iter = error::parse(&contents).expect("failed to parse file");
-        total += display_items(iter, opt.output_format, opt.color_choice)
-            .expect("failed to display errors");
+    if let Some(items) = errors {
+        if let Some(dir) = &opt.bundle_crash {
+            crash::bundle(&items, dir).map_err(|e| DigestError::io(dir, e))?;
+        }
+        total += display_items(items.into_iter(), opt.output_format, opt.color_choice)
+            .map_err(DigestError::Output)?;
+    }
+
+    if let Some(items) = warnings {
+        total += display_items(items.into_iter(), opt.output_format, opt.color_choice)
+            .map_err(DigestError::Output)?;
     }
 
-    if opt.warnings.is_some() {
-        let raw = std::fs::read(opt.warnings.unwrap()).expect("failed to read file");
-        let mut decoder = GzDecoder::new(&*raw);
-        let mut contents = String::new();
-        decoder
-            .read_to_string(&mut contents)
-            .expect("failed to decode file");
-        let iter = warning::parse(&contents).expect("failed to parse file");
-        total += display_items(iter, opt.output_format, opt.color_choice)
-            .expect("failed to display warnings");
+    if let Some(items) = sarif {
+        total += display_items(items.into_iter(), opt.output_format, opt.color_choice)
+            .map_err(DigestError::Output)?;
     }
 
-    Ok(total)
+    match build_status {
+        // a failed build must not look like a clean one, even if nothing could be digested
+        Some(status) if !status.success() => Ok(total.max(1)),
+        _ => Ok(total),
+    }
 }
 
+/// The exit status when the logs could not be digested. Counts of items stop one short of it.
+const EXIT_ERROR: i32 = 0xff;
+
 fn main() {
     exit(match try_main() {
-        Ok(n) if n < 0xff => n as i32,
-        Ok(_) => 0xff,
+        Ok(n) if n < EXIT_ERROR as usize => n as i32,
+        Ok(_) => EXIT_ERROR - 1,
         Err(e) => {
-            eprintln!("{:?}", e);
-            1
+            eprintln!("soong-digest: {}", e);
+            EXIT_ERROR
         }
     });
 }
@@ -0,0 +1,108 @@
+use crate::ansi::strip_ansi_escape;
+use crate::item::{Item, ItemType};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+
+/// Parse errors reported by ninja itself, as opposed to errors reported by the commands ninja
+/// runs. These are not wrapped in FAILED blocks: ninja bails out before running anything.
+pub fn parse(haystack: &str) -> impl Iterator<Item = Item<'_>> {
+    let items = match strip_ansi_escape(haystack) {
+        Cow::Borrowed(haystack) => parse_stripped(haystack),
+        Cow::Owned(haystack) => parse_stripped(&haystack)
+            .into_iter()
+            .map(Item::into_owned)
+            .collect(),
+    };
+    items.into_iter()
+}
+
+fn parse_stripped(haystack: &str) -> Vec<Item<'_>> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(?m)^ninja: error: (.*?)\r?$").unwrap();
+        static ref RE_CYCLE: Regex = Regex::new(r"^dependency cycle: (.*)$").unwrap();
+    }
+    let mut items = vec![];
+    for caps in RE.captures_iter(haystack) {
+        let subject = caps.get(1).unwrap().as_str();
+        let body = RE_CYCLE
+            .captures(subject)
+            .map(|caps| Cow::Owned(format_chain(caps.get(1).unwrap().as_str())));
+        items.push(Item {
+            path: Cow::Borrowed("ninja"),
+            subject: Cow::Borrowed(subject),
+            body,
+            type_: ItemType::Error,
+            ..Default::default()
+        });
+    }
+    items
+}
+
+/// Format a dependency cycle "a -> b -> a" as one target per line, in the order ninja reported
+/// them.
+fn format_chain(chain: &str) -> String {
+    chain
+        .split(" -> ")
+        .enumerate()
+        .map(|(i, target)| match i {
+            0 => format!("    {}", target),
+            _ => format!(" -> {}", target),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_missing_input() {
+        let items = super::parse("ninja: error: 'out/foo.o', needed by 'out/bar', missing and no known rule to make it\n").collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+
+        let i = &items[0];
+        assert_eq!(i.path, "ninja");
+        assert_eq!(i.line, None);
+        assert_eq!(i.column, None);
+        assert_eq!(
+            i.subject,
+            "'out/foo.o', needed by 'out/bar', missing and no known rule to make it"
+        );
+        assert_eq!(i.body, None);
+    }
+
+    #[test]
+    fn test_parse_unknown_target() {
+        let items = super::parse("ninja: error: unknown target 'fooo', did you mean 'foo'?\n")
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].subject,
+            "unknown target 'fooo', did you mean 'foo'?"
+        );
+    }
+
+    #[test]
+    fn test_parse_dependency_cycle() {
+        let items =
+            super::parse("ninja: error: dependency cycle: out/a -> out/b -> out/c -> out/a\n")
+                .collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+
+        let i = &items[0];
+        assert_eq!(
+            i.subject,
+            "dependency cycle: out/a -> out/b -> out/c -> out/a"
+        );
+        assert_eq!(
+            i.body,
+            Some("    out/a\n -> out/b\n -> out/c\n -> out/a".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_other_lines() {
+        let items = super::parse("[1/2] foo\nninja: build stopped: subcommand failed.\n");
+        assert_eq!(items.count(), 0);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::discover;
+use crate::history::Entry;
 use crate::item::{Item, ItemType};
 use std::io::Write;
+use std::path::PathBuf;
 use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
 #[derive(Clone, Copy, Debug)]
@@ -8,50 +11,139 @@ pub enum OutputFormat {
     Cfile,
 }
 
-pub fn display_items<I>(
+pub fn display_items<'a, I>(
     iter: I,
     output_format: OutputFormat,
     color_choice: ColorChoice,
 ) -> std::io::Result<usize>
 where
-    I: Iterator<Item = Item>,
+    I: Iterator<Item = Item<'a>>,
+{
+    let writer = BufferWriter::stdout(color_choice);
+    let mut buffer = writer.buffer();
+    let n = render_items(iter, output_format, &mut buffer)?;
+    writer.print(&buffer)?;
+    Ok(n)
+}
+
+/// Sort and deduplicate the items, and render them into `buffer`. Return the number of items
+/// rendered.
+pub fn render_items<'a, I>(
+    iter: I,
+    output_format: OutputFormat,
+    buffer: &mut Buffer,
+) -> std::io::Result<usize>
+where
+    I: Iterator<Item = Item<'a>>,
 {
     let mut func = match output_format {
         OutputFormat::Full => fill_buffer_full,
         OutputFormat::Cfile => fill_buffer_cfile,
     };
+    fill_buffer(&mut func, buffer, iter)
+}
+
+/// Display the warnings of several builds, each followed by how many of the builds had it and
+/// when it first appeared. `builds` are the logs the entries were read from, newest first.
+pub fn display_history(
+    entries: &[Entry],
+    builds: &[PathBuf],
+    output_format: OutputFormat,
+    color_choice: ColorChoice,
+) -> std::io::Result<usize> {
+    let func = match output_format {
+        OutputFormat::Full => fill_buffer_full,
+        OutputFormat::Cfile => fill_buffer_cfile,
+    };
     let writer = BufferWriter::stdout(color_choice);
     let mut buffer = writer.buffer();
-    let n = fill_buffer(&mut func, &mut buffer, iter)?;
+    for entry in entries {
+        func(&mut buffer, &entry.item)?;
+        fill_buffer_history(&mut buffer, entry, builds)?;
+    }
     writer.print(&buffer)?;
-    Ok(n)
+    Ok(entries.len())
+}
+
+fn fill_buffer_history(
+    mut buffer: &mut Buffer,
+    entry: &Entry,
+    builds: &[PathBuf],
+) -> std::io::Result<()> {
+    let first_seen = match entry.first_seen {
+        0 => "in the latest build".to_string(),
+        1 => "1 build ago".to_string(),
+        n => format!("{} builds ago", n),
+    };
+    let modified = builds
+        .get(entry.first_seen)
+        .and_then(|path| discover::modified_ago(path));
+    buffer.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+    write!(
+        &mut buffer,
+        "seen in {} of {} builds, first seen {}",
+        entry.seen,
+        builds.len(),
+        first_seen
+    )?;
+    if let Some(modified) = modified {
+        write!(&mut buffer, " ({})", modified)?;
+    }
+    buffer.set_color(&ColorSpec::new())?;
+    writeln!(&mut buffer)?;
+    Ok(())
 }
 
-fn fill_buffer<I, F>(func: &mut F, mut buffer: &mut Buffer, iter: I) -> std::io::Result<usize>
+fn fill_buffer<'a, I, F>(func: &mut F, buffer: &mut Buffer, iter: I) -> std::io::Result<usize>
 where
-    I: Iterator<Item = Item>,
+    I: Iterator<Item = Item<'a>>,
     F: FnMut(&mut Buffer, &Item) -> std::io::Result<()>,
 {
     let mut v = iter.collect::<Vec<_>>();
     v.sort();
-    v.dedup();
+    // keep track of all the logs an item was found in
+    v.dedup_by(|a, b| {
+        if a != b {
+            return false;
+        }
+        b.sources.append(&mut a.sources);
+        b.sources.sort();
+        b.sources.dedup();
+        true
+    });
     let total = v.len();
 
     for item in &v {
-        func(&mut buffer, &item)?;
+        func(buffer, item)?;
     }
 
     Ok(total)
 }
 
+/// Invalid UTF-8 in the log is decoded as U+FFFD; flag the items it ended up in, since their text
+/// differs from what the compiler printed.
+fn has_invalid_utf8(item: &Item) -> bool {
+    item.subject.contains('\u{fffd}')
+        || item
+            .body
+            .as_ref()
+            .is_some_and(|body| body.contains('\u{fffd}'))
+}
+
 fn fill_buffer_full(mut buffer: &mut Buffer, item: &Item) -> std::io::Result<()> {
+    if let Some(function) = &item.function {
+        buffer.set_color(ColorSpec::new().set_bold(true))?;
+        write!(&mut buffer, "{}:", item.path)?;
+        buffer.set_color(&ColorSpec::new())?;
+        writeln!(&mut buffer, " In function '{}':", function)?;
+    }
     buffer.set_color(ColorSpec::new().set_bold(true))?;
     write!(&mut buffer, "{}:", item.path)?;
-    if item.line.is_some() {
-        write!(&mut buffer, "{}:", item.line.unwrap())?;
+    if let Some(line) = item.line {
+        write!(&mut buffer, "{}:", line)?;
     }
-    if item.column.is_some() {
-        write!(&mut buffer, "{}:", item.column.unwrap())?;
+    if let Some(column) = item.column {
+        write!(&mut buffer, "{}:", column)?;
     }
     match item.type_ {
         ItemType::Error => {
@@ -62,11 +154,42 @@ fn fill_buffer_full(mut buffer: &mut Buffer, item: &Item) -> std::io::Result<()>
             buffer.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
             write!(&mut buffer, " warning: ")?;
         }
+        ItemType::Crash => {
+            buffer.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            write!(&mut buffer, " crash: ")?;
+        }
+        ItemType::Note => {
+            buffer.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+            write!(&mut buffer, " note: ")?;
+        }
+    }
+    if let Some(failure) = item.failure.filter(|f| f.is_infrastructure()) {
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+        write!(&mut buffer, "[infrastructure: {}] ", failure)?;
+    }
+    if has_invalid_utf8(item) {
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+        write!(&mut buffer, "[invalid UTF-8] ")?;
     }
     buffer.set_color(&ColorSpec::new())?;
     writeln!(&mut buffer, "{}", item.subject)?;
-    if item.body.is_some() {
-        writeln!(&mut buffer, "{}", &item.body.as_ref().unwrap())?;
+    if let Some(body) = &item.body {
+        writeln!(&mut buffer, "{}", body)?;
+    }
+    if let Some(crash) = &item.crash {
+        writeln!(&mut buffer, "command: {}", crash.command)?;
+        for path in &crash.reproducers {
+            writeln!(&mut buffer, "reproducer: {}", path)?;
+        }
+    }
+    for source in &item.sources {
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+        write!(&mut buffer, "from: {}", source.log)?;
+        if let Some(product) = &source.product {
+            write!(&mut buffer, " ({})", product)?;
+        }
+        buffer.set_color(&ColorSpec::new())?;
+        writeln!(&mut buffer)?;
     }
     Ok(())
 }
@@ -74,11 +197,11 @@ fn fill_buffer_full(mut buffer: &mut Buffer, item: &Item) -> std::io::Result<()>
 fn fill_buffer_cfile(mut buffer: &mut Buffer, item: &Item) -> std::io::Result<()> {
     buffer.set_color(ColorSpec::new().set_bold(true))?;
     write!(&mut buffer, "{}:", item.path)?;
-    if item.line.is_some() {
-        write!(&mut buffer, "{}:", item.line.unwrap())?;
+    if let Some(line) = item.line {
+        write!(&mut buffer, "{}:", line)?;
     }
-    if item.column.is_some() {
-        write!(&mut buffer, "{}:", item.column.unwrap())?;
+    if let Some(column) = item.column {
+        write!(&mut buffer, "{}:", column)?;
     }
     match item.type_ {
         ItemType::Error => {
@@ -89,6 +212,22 @@ fn fill_buffer_cfile(mut buffer: &mut Buffer, item: &Item) -> std::io::Result<()
             buffer.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
             write!(&mut buffer, " warning: ")?;
         }
+        ItemType::Crash => {
+            buffer.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            write!(&mut buffer, " crash: ")?;
+        }
+        ItemType::Note => {
+            buffer.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+            write!(&mut buffer, " note: ")?;
+        }
+    }
+    if let Some(failure) = item.failure.filter(|f| f.is_infrastructure()) {
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+        write!(&mut buffer, "[infrastructure: {}] ", failure)?;
+    }
+    if has_invalid_utf8(item) {
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+        write!(&mut buffer, "[invalid UTF-8] ")?;
     }
     buffer.set_color(&ColorSpec::new())?;
     writeln!(&mut buffer, "{}", item.subject)?;
@@ -98,12 +237,14 @@ fn fill_buffer_cfile(mut buffer: &mut Buffer, item: &Item) -> std::io::Result<()
 #[cfg(test)]
 mod tests {
     use crate::error;
+    use crate::item::Source;
+    use crate::warning;
     use termcolor::{BufferWriter, ColorChoice};
 
     #[test]
     fn test_group_identical_items() {
         let haystack = include_str!("../tests/data/idmap-identical-errors/error.log");
-        let items = error::parse(&haystack).unwrap();
+        let items = error::parse(haystack).unwrap();
         let writer = BufferWriter::stdout(ColorChoice::Never);
         let mut buffer = writer.buffer();
         super::fill_buffer(&mut super::fill_buffer_full, &mut buffer, items).unwrap();
@@ -111,4 +252,47 @@ mod tests {
         let s = String::from_utf8_lossy(&v);
         assert_eq!(s.matches("frameworks/base/libs/androidfw/misc.cpp:40:9: error: no matching function for call to 'stat'").count(), 1);
     }
+
+    #[test]
+    fn test_merge_sources() {
+        let haystack = "foo.c:1:2: warning: bar\n";
+        let source = |log: &str, product: &str| Source {
+            log: log.to_string(),
+            product: Some(product.to_string()),
+        };
+        let items = warning::parse(haystack)
+            .unwrap()
+            .map(|mut item| {
+                item.sources = vec![source("a/verbose.log.gz", "walleye")];
+                item
+            })
+            .chain(warning::parse(haystack).unwrap().map(|mut item| {
+                item.sources = vec![source("b/verbose.log.gz", "taimen")];
+                item
+            }));
+        let writer = BufferWriter::stdout(ColorChoice::Never);
+        let mut buffer = writer.buffer();
+        let n = super::fill_buffer(&mut super::fill_buffer_full, &mut buffer, items).unwrap();
+        assert_eq!(n, 1);
+        let v = buffer.into_inner();
+        assert_eq!(
+            String::from_utf8_lossy(&v),
+            "foo.c:1:2: warning: bar\n\
+             from: a/verbose.log.gz (walleye)\n\
+             from: b/verbose.log.gz (taimen)\n"
+        );
+    }
+
+    #[test]
+    fn test_mark_invalid_utf8() {
+        let haystack = "foo.c:1:2: warning: bar\n  \"caf\u{fffd}\"\nfoo.c:3:4: warning: baz\n";
+        let items = warning::parse(haystack).unwrap();
+        let writer = BufferWriter::stdout(ColorChoice::Never);
+        let mut buffer = writer.buffer();
+        super::fill_buffer(&mut super::fill_buffer_full, &mut buffer, items).unwrap();
+        let v = buffer.into_inner();
+        let s = String::from_utf8_lossy(&v);
+        assert!(s.contains("foo.c:1:2: warning: [invalid UTF-8] bar"));
+        assert!(s.contains("foo.c:3:4: warning: baz"));
+    }
 }
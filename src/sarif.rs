@@ -0,0 +1,280 @@
+use crate::digest_error::ParseError;
+use crate::item::{FixIt, Item, ItemType, Span};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Parse a SARIF log, such as the one clang writes with -fdiagnostics-format=sarif.
+pub fn parse(haystack: &str) -> Result<impl Iterator<Item = Item<'static>>, ParseError> {
+    let log: Value = serde_json::from_str(haystack).map_err(|e| ParseError {
+        line: Some(e.line()).filter(|&line| line > 0),
+        text: haystack
+            .lines()
+            .nth(e.line().saturating_sub(1))
+            .unwrap_or("")
+            .to_string(),
+        message: format!("failed to parse SARIF: {}", e),
+    })?;
+    let mut items = vec![];
+    for run in array(&log["runs"]) {
+        for result in array(&run["results"]) {
+            // the JSON values do not know where they came from, so there is no line to report
+            let item = parse_result(result).map_err(|e| ParseError::new(&e))?;
+            if let Some(item) = item {
+                items.push(item);
+            }
+        }
+    }
+    Ok(items.into_iter())
+}
+
+/// Find all .sarif files in a directory tree, such as out/soong/.intermediates. A path to a file
+/// is returned as is. Symlinks inside the tree are not followed, as they may form a loop.
+pub fn find_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            files.extend(find_files(&path)?);
+        } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == "sarif") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn parse_result(result: &Value) -> Result<Option<Item<'static>>, String> {
+    let type_ = match result["level"].as_str() {
+        Some("error") => ItemType::Error,
+        // "warning" is the default level
+        Some("warning") | None => ItemType::Warning,
+        // notes and remarks only make sense next to the diagnostic they belong to
+        Some(_) => return Ok(None),
+    };
+    let subject = result["message"]["text"]
+        .as_str()
+        .ok_or_else(|| "SARIF result without a message".to_string())?;
+    let location = &result["locations"][0]["physicalLocation"];
+    let span = parse_region(&location["region"]);
+    let notes = array(&result["relatedLocations"])
+        .iter()
+        .filter_map(parse_note)
+        .collect::<Vec<_>>();
+    let fixits = array(&result["fixes"])
+        .iter()
+        .flat_map(|fix| array(&fix["artifactChanges"]))
+        .flat_map(parse_artifact_change)
+        .collect();
+    Ok(Some(Item {
+        path: parse_uri(&location["artifactLocation"]).into(),
+        line: span.as_ref().map(|s| s.start_line),
+        column: span.as_ref().map(|s| s.start_column),
+        subject: subject.to_string().into(),
+        body: match notes.len() {
+            0 => None,
+            _ => Some(notes.join("\n").into()),
+        },
+        type_,
+        spans: span.into_iter().collect(),
+        fixits,
+        ..Default::default()
+    }))
+}
+
+/// Format a related location the way clang prints notes in its text output.
+fn parse_note(location: &Value) -> Option<String> {
+    let text = location["message"]["text"].as_str()?;
+    let physical = &location["physicalLocation"];
+    let mut note = parse_uri(&physical["artifactLocation"]);
+    if let Some(span) = parse_region(&physical["region"]) {
+        note += &format!(":{}:{}", span.start_line, span.start_column);
+    }
+    Some(format!("{}: note: {}", note, text))
+}
+
+fn parse_artifact_change(change: &Value) -> Vec<FixIt> {
+    let path = parse_uri(&change["artifactLocation"]);
+    array(&change["replacements"])
+        .iter()
+        .filter_map(|replacement| {
+            Some(FixIt {
+                path: path.clone(),
+                span: parse_region(&replacement["deletedRegion"])?,
+                replacement: replacement["insertedContent"]["text"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_region(region: &Value) -> Option<Span> {
+    let start_line = region["startLine"].as_u64()? as usize;
+    let start_column = region["startColumn"].as_u64().unwrap_or(1) as usize;
+    Some(Span {
+        start_line,
+        start_column,
+        end_line: region["endLine"]
+            .as_u64()
+            .map_or(start_line, |x| x as usize),
+        // a region without an end column runs to the end of its last line
+        end_column: region["endColumn"]
+            .as_u64()
+            .map_or(Span::END_OF_LINE, |x| x as usize),
+    })
+}
+
+/// Convert an artifact location to a path relative to the source tree. Soong runs clang with
+/// PWD=/proc/self/cwd, so that is the prefix of all absolute paths clang reports.
+fn parse_uri(location: &Value) -> String {
+    let uri = location["uri"].as_str().unwrap_or("");
+    let path = uri.trim_start_matches("file://");
+    let path = path.trim_start_matches("/proc/self/cwd/");
+    percent_decode(path)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = match bytes[i] {
+            b'%' if i + 2 < bytes.len() => std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn array(value: &Value) -> &[Value] {
+    value.as_array().map_or(&[], |v| v.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::item::{FixIt, ItemType, Span};
+    use std::fs;
+
+    #[test]
+    fn test_parse_errors() {
+        let haystack = include_str!("../tests/data/idmap-sarif/obj/create/create.o.sarif");
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+
+        let i = &items[0];
+        assert_eq!(i.path, "frameworks/base/cmds/idmap/create.cpp");
+        assert_eq!(i.line, Some(29));
+        assert_eq!(i.column, Some(33));
+        assert_eq!(i.subject, "expected ';' after expression");
+        assert_eq!(i.body, None);
+        assert_eq!(i.type_, ItemType::Error);
+        assert_eq!(
+            i.spans,
+            vec![Span {
+                start_line: 29,
+                start_column: 33,
+                end_line: 29,
+                end_column: 33,
+            }]
+        );
+        assert_eq!(
+            i.fixits,
+            vec![FixIt {
+                path: "frameworks/base/cmds/idmap/create.cpp".to_string(),
+                span: Span {
+                    start_line: 29,
+                    start_column: 33,
+                    end_line: 29,
+                    end_column: 33,
+                },
+                replacement: ";".to_string(),
+            }]
+        );
+
+        let i = &items[1];
+        assert_eq!(i.line, Some(89));
+        assert_eq!(i.column, Some(13));
+        assert_eq!(i.subject, "no matching function for call to 'lseek'");
+        assert_eq!(i.body, Some("bionic/libc/include/unistd.h:258:7: note: candidate function not viable: requires 3 arguments, but 2 were provided".into()));
+        assert_eq!(i.spans[0].end_column, 18);
+        assert_eq!(i.fixits, vec![]);
+    }
+
+    #[test]
+    fn test_parse_warnings() {
+        let haystack = include_str!("../tests/data/idmap-sarif/obj/idmap/idmap.o.sarif");
+        let items = super::parse(haystack).unwrap().collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+
+        let i = &items[0];
+        assert_eq!(i.path, "frameworks/base/cmds/idmap/idmap.cpp");
+        assert_eq!(i.subject, "unused variable 'ret' [-Wunused-variable]");
+        assert_eq!(i.type_, ItemType::Warning);
+    }
+
+    #[test]
+    fn test_parse_invalid_json() {
+        let result = super::parse("FAILED: foo");
+        assert!(result.is_err());
+        let e = result.err().unwrap();
+        assert!(e.message.contains("failed to parse SARIF"));
+        assert_eq!((e.line, e.text.as_str()), (Some(1), "FAILED: foo"));
+
+        let e = super::parse(r#"{"runs": [{"results": [{"level": "error"}]}]}"#)
+            .err()
+            .unwrap();
+        assert_eq!(e.line, None);
+        assert_eq!(e.message, "SARIF result without a message");
+    }
+
+    #[test]
+    fn test_parse_region_to_end_of_line() {
+        let region = serde_json::json!({"startLine": 3, "startColumn": 5});
+        assert_eq!(
+            super::parse_region(&region),
+            Some(Span {
+                start_line: 3,
+                start_column: 5,
+                end_line: 3,
+                end_column: Span::END_OF_LINE,
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_files_skips_symlinks() {
+        let dir = std::env::temp_dir().join(format!("soong-digest-sarif-{}", std::process::id()));
+        fs::create_dir_all(dir.join("obj")).unwrap();
+        fs::write(dir.join("obj/foo.o.sarif"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("obj/loop")).unwrap();
+        let files = super::find_files(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("obj/foo.o.sarif")]);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(super::percent_decode("foo%20bar.c"), "foo bar.c");
+        assert_eq!(super::percent_decode("100%"), "100%");
+    }
+}
@@ -1,86 +1,202 @@
 use crate::ansi::strip_ansi_escape;
-use crate::item::{Item, ItemType};
+use crate::caret;
+use crate::digest_error::ParseError;
+use crate::fixit;
+use crate::gcc;
+use crate::item::{self, FixIt, Item, ItemType};
 use lazy_static::lazy_static;
+use memchr::memmem;
+use rayon::prelude::*;
 use regex::Regex;
-use std::convert::From;
+use std::borrow::Cow;
 
-pub fn parse<'a, 'b>(haystack: &'a str) -> Result<impl Iterator<Item = Item> + 'b, String> {
-    let mut items: Vec<Item> = Vec::new();
-    let haystack = strip_ansi_escape(haystack);
-    let captures = find_captures(&haystack);
-    for c in captures {
-        items.push(Item::from(c));
-    }
+/// The approximate size of the chunks of a log that are parsed in parallel.
+const CHUNK_SIZE: usize = 1 << 20;
+
+pub fn parse(haystack: &str) -> Result<impl Iterator<Item = Item<'_>>, ParseError> {
+    let items = match strip_ansi_escape(haystack) {
+        Cow::Borrowed(haystack) => parse_stripped(haystack)?,
+        Cow::Owned(haystack) => parse_stripped(&haystack)?
+            .into_iter()
+            .map(Item::into_owned)
+            .collect(),
+    };
     Ok(items.into_iter())
 }
 
+fn parse_stripped(haystack: &str) -> Result<Vec<Item<'_>>, ParseError> {
+    let mut items: Vec<Item> = Vec::new();
+    let captures = find_captures(haystack)?;
+    items.par_extend(captures.into_par_iter().map(|c| c.into_item(haystack)));
+    Ok(items)
+}
+
+#[derive(Debug, PartialEq)]
 struct Captures<'h> {
-    head: &'h str,
+    path: &'h str,
+    line: usize,
+    column: usize,
+    subject: &'h str,
     body: Vec<&'h str>,
+    function: Option<&'h str>,
+    fixits: Vec<FixIt>,
 }
 
-impl<'h> From<Captures<'h>> for Item {
-    fn from(captures: Captures<'h>) -> Self {
-        lazy_static! {
-            static ref re: Regex = Regex::new(r"(\S+):(\d+):(\d+): warning: (.*)").unwrap();
-        }
-        let caps = re.captures(&captures.head).unwrap();
+impl<'h> Captures<'h> {
+    /// `haystack` is the log the captures were found in.
+    fn into_item(self, haystack: &'h str) -> Item<'h> {
+        let path = self.path;
+        let line = self.line;
         Item {
-            path: caps.get(1).unwrap().as_str().to_string(),
-            line: Some(caps.get(2).unwrap().as_str().parse().unwrap()),
-            column: Some(caps.get(3).unwrap().as_str().parse().unwrap()),
-            subject: caps.get(4).unwrap().as_str().to_string(),
-            body: match captures.body.len() {
-                0 => None,
-                _ => Some(captures.body.join("\n")),
-            },
+            path: Cow::Borrowed(path),
+            line: Some(line),
+            column: Some(self.column),
+            subject: Cow::Borrowed(self.subject),
+            body: item::join_lines(haystack, &self.body),
             type_: ItemType::Warning,
+            function: self.function.map(Cow::Borrowed),
+            spans: caret::spans(line, &self.body),
+            fixits: match self.fixits.len() {
+                0 => fixit::from_hint(path, line, &self.body)
+                    .into_iter()
+                    .collect(),
+                _ => self.fixits,
+            },
+            ..Default::default()
         }
     }
 }
 
-fn find_captures(haystack: &str) -> Vec<Captures> {
+fn find_captures(haystack: &str) -> Result<Vec<Captures<'_>>, ParseError> {
+    let captures = chunks(haystack, CHUNK_SIZE)
+        .into_par_iter()
+        .map(|chunk| find_captures_sequential(chunk).map_err(|e| e.within(haystack, chunk)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(captures.into_iter().flatten().collect())
+}
+
+/// Split a log into chunks of at least `size` bytes. Each chunk but the first starts at a ninja
+/// progress line, which ends any capture and resets the GCC context, so the chunks can be parsed
+/// independently of each other.
+fn chunks(haystack: &str, size: usize) -> Vec<&str> {
     lazy_static! {
-        static ref re_subject: Regex = Regex::new(r"^\S+:\d+:\d+: warning: .*$").unwrap();
-        static ref re_noise: Regex = Regex::new(r"^\[\d+/\d+\]").unwrap();
+        static ref RE_STEP: Regex = Regex::new(r"(?m)^\[\d+/\d+\] ").unwrap();
+    }
+    let mut chunks = vec![];
+    let mut start = 0;
+    while haystack.len() - start > size {
+        // continue the search at the start of a line, or ^ would match in the middle of one
+        let next_line = match haystack[start + size..].find('\n') {
+            Some(i) => start + size + i + 1,
+            None => break,
+        };
+        match RE_STEP.find(&haystack[next_line..]) {
+            Some(m) => {
+                chunks.push(&haystack[start..next_line + m.start()]);
+                start = next_line + m.start();
+            }
+            None => break,
+        }
+    }
+    chunks.push(&haystack[start..]);
+    chunks
+}
+
+/// Most lines of a log are neither warnings nor progress lines, so each regex is only run on lines
+/// that contain the literal text it requires.
+fn find_captures_sequential(haystack: &str) -> Result<Vec<Captures<'_>>, ParseError> {
+    lazy_static! {
+        static ref WARNING: memmem::Finder<'static> = memmem::Finder::new(": warning: ");
+        static ref RE_LOCATION: Regex = Regex::new(r"^(\S+):(\d+):(\d+)$").unwrap();
+        static ref RE_NOISE: Regex = Regex::new(r"^\[\d+/\d+\]").unwrap();
+        // errors are reported by the error parser, and must not end up in the body of the
+        // warning before them
+        static ref RE_END: Regex =
+            Regex::new(r"^(?:FAILED: |\S+:(?:\d+:){0,2} (?:fatal )?error: )").unwrap();
+    }
+    if WARNING.find(haystack.as_bytes()).is_none() {
+        return Ok(vec![]);
     }
     let mut captures = Vec::new();
     let mut current: Option<Captures> = None;
+    let mut context = gcc::Context::default();
     for line in haystack.lines() {
-        if re_subject.is_match(line) {
-            if current.is_some() {
-                captures.push(current.take().unwrap());
+        // the location is everything before the first ": warning: ", as it cannot contain spaces
+        let subject = WARNING.find(line.as_bytes()).and_then(|i| {
+            RE_LOCATION
+                .captures(&line[..i])
+                .map(|caps| (caps, &line[i + WARNING.needle().len()..]))
+        });
+        if let Some((caps, subject)) = subject {
+            if let Some(c) = current.take() {
+                captures.push(c);
             }
+            let path = caps.get(1).unwrap().as_str();
+            let number =
+                |i| {
+                    caps.get(i).unwrap().as_str().parse().map_err(|_| {
+                        ParseError::at(haystack, line, "invalid line or column number")
+                    })
+                };
             current = Some(Captures {
-                head: line,
+                path,
+                line: number(2)?,
+                column: number(3)?,
+                subject,
                 body: vec![],
+                function: context.function(path),
+                fixits: vec![],
             });
             continue;
         }
-        if re_noise.is_match(line) {
-            if current.is_some() {
-                captures.push(current.take().unwrap());
+        if line.starts_with('[') && RE_NOISE.is_match(line) {
+            if let Some(c) = current.take() {
+                captures.push(c);
             }
+            context.reset();
             continue;
         }
-        if current.is_some() {
-            let mut c = current.unwrap();
-            c.body.push(line);
-            current = Some(c);
+        if context.update(line) {
+            if let Some(c) = current.take() {
+                captures.push(c);
+            }
+            continue;
+        }
+        if current.is_none() {
+            continue;
+        }
+        if gcc::is_noise(line)
+            || (line.contains("FAILED: ") || line.contains("error: ")) && RE_END.is_match(line)
+        {
+            if let Some(c) = current.take() {
+                captures.push(c);
+            }
+            continue;
+        }
+        if let Some(c) = current.as_mut() {
+            match fixit::parse(line) {
+                Some(fixit) => c.fixits.push(fixit),
+                None => c.body.push(line),
+            }
             continue;
         }
     }
-    if current.is_some() {
-        captures.push(current.unwrap());
+    if let Some(c) = current {
+        captures.push(c);
     }
-    captures
+    Ok(captures)
 }
 
 #[cfg(test)]
 mod tests {
     use flate2::read::GzDecoder;
+    use std::borrow::Cow;
     use std::io::Read;
 
+    fn head(c: &super::Captures) -> String {
+        format!("{}:{}:{}: warning: {}", c.path, c.line, c.column, c.subject)
+    }
+
     fn uncompress_test_data() -> String {
         let raw: &[u8] = include_bytes!("../tests/data/easter-egg-errors-java/verbose.log.gz");
         let mut decoder = GzDecoder::new(raw);
@@ -91,25 +207,26 @@ mod tests {
 
     #[test]
     fn test_find_captures_empty() {
-        let captures = super::find_captures("");
+        let captures = super::find_captures("").unwrap();
         assert_eq!(captures.len(), 0);
     }
 
     #[test]
     fn test_find_captures_single_line_no_noise() {
-        let captures = super::find_captures("foo.c:10:20: warning: bar");
+        let captures = super::find_captures("foo.c:10:20: warning: bar").unwrap();
         assert_eq!(captures.len(), 1);
         let c = &captures[0];
-        assert_eq!(c.head, "foo.c:10:20: warning: bar");
+        assert_eq!(head(c), "foo.c:10:20: warning: bar");
         assert_eq!(c.body, Vec::<&str>::new());
     }
 
     #[test]
     fn test_find_captures_multiple_lines_no_noise() {
-        let captures = super::find_captures("foo.c:10:20: warning: bar\nbody line 1\nbody line 2");
+        let captures =
+            super::find_captures("foo.c:10:20: warning: bar\nbody line 1\nbody line 2").unwrap();
         assert_eq!(captures.len(), 1);
         let c = &captures[0];
-        assert_eq!(c.head, "foo.c:10:20: warning: bar");
+        assert_eq!(head(c), "foo.c:10:20: warning: bar");
         assert_eq!(c.body, vec!["body line 1", "body line 2"]);
     }
 
@@ -117,35 +234,95 @@ mod tests {
     fn test_find_captures_multiple_warnings_no_noise() {
         let captures = super::find_captures(
             "foo.c:10:20: warning: bar\nfoo 1\nbar.c:30:40: warning: foo\nbar 1",
-        );
+        )
+        .unwrap();
         assert_eq!(captures.len(), 2);
 
         let c = &captures[0];
-        assert_eq!(c.head, "foo.c:10:20: warning: bar");
+        assert_eq!(head(c), "foo.c:10:20: warning: bar");
         assert_eq!(c.body, vec!["foo 1"]);
 
         let c = &captures[1];
-        assert_eq!(c.head, "bar.c:30:40: warning: foo");
+        assert_eq!(head(c), "bar.c:30:40: warning: foo");
         assert_eq!(c.body, vec!["bar 1"]);
     }
 
+    #[test]
+    fn test_find_captures_warning_text_elsewhere() {
+        let captures = super::find_captures(
+            "[1/2] echo foo.c:10:20: warning: bar\n\
+             foo.c:10: warning: bar\n\
+             foo.c:10:20: warning: bar: warning: baz",
+        )
+        .unwrap();
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].subject, "bar: warning: baz");
+    }
+
     #[test]
     fn test_find_captures_single_line_surrounded_by_noise() {
-        let captures = super::find_captures("[1/2] foo\nfoo.c:10:20: warning: bar\n[2/2] bar");
+        let captures =
+            super::find_captures("[1/2] foo\nfoo.c:10:20: warning: bar\n[2/2] bar").unwrap();
         assert_eq!(captures.len(), 1);
         let c = &captures[0];
-        assert_eq!(c.head, "foo.c:10:20: warning: bar");
+        assert_eq!(head(c), "foo.c:10:20: warning: bar");
         assert_eq!(c.body, Vec::<&str>::new());
     }
 
+    #[test]
+    fn test_find_captures_stops_at_errors() {
+        let captures = super::find_captures(
+            "foo.c:10:20: warning: bar\n\
+             foo 1\n\
+             foo.c:12:5: error: baz\n\
+             baz 1\n\
+             FAILED: foo.o",
+        )
+        .unwrap();
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].body, vec!["foo 1"]);
+    }
+
+    #[test]
+    fn test_find_captures_gcc_output() {
+        let captures = super::find_captures(
+            "[1/2] gcc -c foo.c\n\
+             In file included from foo.c:1:\n\
+             foo.h: In function 'foo':\n\
+             foo.h:3:20: warning: 'x' may be used uninitialized [-Wmaybe-uninitialized]\n\
+             foo.h:5:9: note: in expansion of macro 'BAR'\n\
+             foo.c: In function 'main':\n\
+             foo.c:10:5: warning: '__builtin_memcpy' writing 8 bytes [-Wstringop-overflow=]\n\
+             [2/2] gcc -c bar.c\n\
+             foo.c:20:5: warning: unused variable 'y' [-Wunused-variable]",
+        )
+        .unwrap();
+        assert_eq!(captures.len(), 3);
+
+        let c = &captures[0];
+        assert_eq!(
+            head(c),
+            "foo.h:3:20: warning: 'x' may be used uninitialized [-Wmaybe-uninitialized]"
+        );
+        assert_eq!(c.body, vec!["foo.h:5:9: note: in expansion of macro 'BAR'"]);
+        assert_eq!(c.function, Some("foo"));
+
+        let c = &captures[1];
+        assert_eq!(c.body, Vec::<&str>::new());
+        assert_eq!(c.function, Some("main"));
+
+        let c = &captures[2];
+        assert_eq!(c.function, None);
+    }
+
     #[test]
     fn test_find_captures_actual_soong_output() {
         let contents = uncompress_test_data();
-        let captures = super::find_captures(&contents);
+        let captures = super::find_captures(&contents).unwrap();
         assert_eq!(captures.len(), 9);
 
         let c = &captures[0];
-        assert_eq!(c.head, "frameworks/base/packages/EasterEgg/src/com/android/egg/paint/CutoutAvoidingToolbar.kt:85:22: warning: parameter 'attrs' is never used");
+        assert_eq!(head(c), "frameworks/base/packages/EasterEgg/src/com/android/egg/paint/CutoutAvoidingToolbar.kt:85:22: warning: parameter 'attrs' is never used");
         assert_eq!(
             c.body,
             vec![
@@ -155,6 +332,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_captures_invalid_number() {
+        let e = super::find_captures("[1/2] foo\nfoo.c:99999999999999999999:1: warning: bar\n")
+            .unwrap_err();
+        assert_eq!(e.line, Some(2));
+        assert_eq!(e.text, "foo.c:99999999999999999999:1: warning: bar");
+    }
+
+    #[test]
+    fn test_chunks() {
+        let haystack = "foo.c:1:2: warning: foo\n[1/2] bar\nbar.c:3:4: warning: bar\n[2/2] baz\n";
+        assert_eq!(super::chunks(haystack, 1 << 20), vec![haystack]);
+        assert_eq!(
+            super::chunks(haystack, 1),
+            vec![
+                "foo.c:1:2: warning: foo\n",
+                "[1/2] bar\nbar.c:3:4: warning: bar\n",
+                "[2/2] baz\n"
+            ]
+        );
+        assert_eq!(
+            super::chunks(haystack, 30),
+            vec![
+                "foo.c:1:2: warning: foo\n[1/2] bar\nbar.c:3:4: warning: bar\n",
+                "[2/2] baz\n"
+            ]
+        );
+        assert_eq!(super::chunks("", 1), vec![""]);
+    }
+
+    #[test]
+    fn test_find_captures_in_parallel() {
+        let contents = uncompress_test_data();
+        let sequential = super::find_captures_sequential(&contents).unwrap();
+        for size in &[1, 100, 1000] {
+            let chunks = super::chunks(&contents, *size);
+            assert!(chunks.len() > 1);
+            assert_eq!(chunks.concat(), contents);
+            let parallel = chunks
+                .into_iter()
+                .flat_map(|chunk| super::find_captures_sequential(chunk).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(parallel, sequential);
+        }
+        assert_eq!(super::find_captures(&contents).unwrap(), sequential);
+    }
+
     #[test]
     fn test_parse() {
         let items = super::parse("[1/2] foo\nfoo.c:10:20: warning: bar\nbody 1\nbody 2\n[2/2] bar")
@@ -167,7 +391,14 @@ mod tests {
         assert_eq!(item.line, Some(10));
         assert_eq!(item.column, Some(20));
         assert_eq!(item.subject, "bar");
-        assert_eq!(item.body, Some("body 1\nbody 2".to_string()));
+        assert_eq!(item.body, Some("body 1\nbody 2".into()));
+        assert!(matches!(item.body, Some(Cow::Borrowed(_))));
+
+        let items = super::parse("\u{1b}[1mfoo.c:10:20: warning: bar\u{1b}[0m\nbody 1")
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(items[0].subject, "bar");
+        assert_eq!(items[0].body, Some("body 1".into()));
     }
 
     #[test]
@@ -186,7 +417,10 @@ mod tests {
         assert_eq!(item.subject, "parameter 'attrs' is never used");
         assert_eq!(
             item.body,
-            Some("    private fun init(attrs: AttributeSet?, defStyle: Int) {\n                     ^".to_string())
+            Some("    private fun init(attrs: AttributeSet?, defStyle: Int) {\n                     ^".into())
         );
+        assert_eq!(item.spans.len(), 1);
+        assert_eq!(item.spans[0].start_column, 22);
+        assert_eq!(item.spans[0].end_column, 23);
     }
 }